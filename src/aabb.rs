@@ -0,0 +1,88 @@
+use crate::ray::*;
+use crate::vec3::*;
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: vec3!(f32::MAX),
+        max: vec3!(-f32::MAX),
+    };
+
+    #[inline(always)]
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Self {
+            min: Vec3::min(a, b),
+            max: Vec3::max(a, b),
+        }
+    }
+
+    #[inline(always)]
+    pub fn union(self, other: Aabb) -> Self {
+        Self {
+            min: Vec3::min(self.min, other.min),
+            max: Vec3::max(self.max, other.max),
+        }
+    }
+
+    #[inline(always)]
+    pub fn grow(self, p: Vec3) -> Self {
+        Self {
+            min: Vec3::min(self.min, p),
+            max: Vec3::max(self.max, p),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    #[inline(always)]
+    pub fn centroid(self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    #[inline(always)]
+    pub fn extent(self) -> Vec3 {
+        self.max - self.min
+    }
+
+    #[inline(always)]
+    pub fn surface_area(self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    #[inline(always)]
+    pub fn largest_axis(self) -> usize {
+        let e = self.extent();
+        if e.x > e.y && e.x > e.z {
+            0
+        } else if e.y > e.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test. `inv_dir` is the component-wise reciprocal of the ray
+    /// direction, computed once per ray by the caller.
+    #[inline(always)]
+    pub fn hit(&self, r: Ray, inv_dir: Vec3, t_min: f32, t_max: f32) -> bool {
+        let t0 = Vec3::hadamard(self.min - r.origin, inv_dir);
+        let t1 = Vec3::hadamard(self.max - r.origin, inv_dir);
+        let near = Vec3::min(t0, t1);
+        let far = Vec3::max(t0, t1);
+        let t_enter = near.x.max(near.y).max(near.z).max(t_min);
+        let t_exit = far.x.min(far.y).min(far.z).min(t_max);
+        t_enter <= t_exit
+    }
+}
@@ -0,0 +1,96 @@
+use crate::hittable::*;
+use crate::random::*;
+use crate::ray::*;
+use crate::texture::*;
+use std::sync::Arc;
+
+/// How an alpha value decides whether a ray hits the surface.
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    /// The surface is there where alpha reaches the threshold: hard edges,
+    /// no noise.
+    Threshold,
+    /// Rays hit with probability alpha, which renders partial coverage
+    /// correctly on average.
+    Stochastic,
+}
+
+impl AlphaMode {
+    pub const NAMES: &'static str = "threshold, stochastic";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "threshold" => Some(Self::Threshold),
+            "stochastic" => Some(Self::Stochastic),
+            _ => None,
+        }
+    }
+}
+
+/// Opacity of a material, for cutouts like leaves and fences. The scene
+/// tests the mask of a shape's material during intersection, so rays
+/// (shadow rays included) pass through where the surface is cut out.
+pub struct AlphaMask {
+    /// Read through `Texture::scalar`.
+    pub alpha: Arc<dyn Texture>,
+    pub mode: AlphaMode,
+    pub threshold: f32,
+}
+
+impl AlphaMask {
+    pub fn new(alpha: Arc<dyn Texture>, mode: AlphaMode, threshold: f32) -> Self {
+        Self {
+            alpha,
+            mode,
+            threshold,
+        }
+    }
+
+    /// Whether ray `r` hits the surface at `rec`.
+    #[inline(always)]
+    pub fn is_opaque(&self, r: Ray, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.scalar(rec);
+        match self.mode {
+            AlphaMode::Threshold => alpha >= self.threshold,
+            AlphaMode::Stochastic => {
+                if alpha >= 1.0 {
+                    return true;
+                }
+                if alpha <= 0.0 {
+                    return false;
+                }
+                // Hashing rather than drawing from a generator gives the
+                // same answer however often the hit is tested, and
+                // independent answers for the surfaces along one ray.
+                let (o, d) = (r.origin, r.direction);
+                hash_floats(&[o.x, o.y, o.z, d.x, d.y, d.z, rec.t]) < alpha
+            }
+        }
+    }
+}
+
+/// Writes a hit into `rec` through `record`, unless `alpha` cuts the surface
+/// out there. A rejected hit leaves `rec` untouched.
+#[inline(always)]
+pub fn record_hit(
+    alpha: Option<&AlphaMask>,
+    r: Ray,
+    rec: &mut HitRecord,
+    record: impl FnOnce(&mut HitRecord),
+) -> bool {
+    match alpha {
+        None => {
+            record(rec);
+            true
+        }
+        Some(alpha) => {
+            let mut candidate = *rec;
+            record(&mut candidate);
+            if !alpha.is_opaque(r, &candidate) {
+                return false;
+            }
+            *rec = candidate;
+            true
+        }
+    }
+}
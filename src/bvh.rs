@@ -0,0 +1,414 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::time::{Duration, Instant};
+
+const BIN_COUNT: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const MAX_DEPTH: usize = 64;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+/// Node of the flattened tree. Nodes are stored depth-first, so the first
+/// child of an interior node is always the node right after it.
+#[derive(Clone, Copy)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    /// Leaf: index of the first primitive in `Bvh::indices`.
+    /// Interior: index of the second child.
+    pub offset: u32,
+    /// Number of primitives in a leaf, 0 for interior nodes.
+    pub count: u16,
+    /// Split axis of interior nodes, used to visit the nearest child first.
+    pub axis: u16,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct BvhStats {
+    pub build_time: Duration,
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+}
+
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub indices: Vec<u32>,
+}
+
+struct BuildPrimitive {
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+impl Bvh {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            indices: vec![],
+        }
+    }
+
+    /// Builds the tree over the given primitive bounds using the surface area
+    /// heuristic. Primitive `i` is referred to by index `i` during traversal.
+    pub fn build(bounds: &[Aabb]) -> (Self, BvhStats) {
+        let before = Instant::now();
+
+        let primitives: Vec<BuildPrimitive> = bounds
+            .iter()
+            .map(|&bounds| BuildPrimitive {
+                bounds,
+                centroid: bounds.centroid(),
+            })
+            .collect();
+
+        let mut indices: Vec<u32> = (0..bounds.len() as u32).collect();
+        let mut nodes = Vec::with_capacity(2 * bounds.len());
+        let mut stats = BvhStats {
+            primitive_count: bounds.len(),
+            ..Default::default()
+        };
+
+        if !indices.is_empty() {
+            build_recursive(&primitives, &mut indices, 0, 0, &mut nodes, &mut stats);
+        }
+
+        stats.node_count = nodes.len();
+        stats.build_time = before.elapsed();
+
+        (Self { nodes, indices }, stats)
+    }
+
+    #[inline(always)]
+    pub fn bounds(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => Aabb::EMPTY,
+        }
+    }
+
+    /// Finds the closest hit. `hit_primitive` is called with the primitive
+    /// index and the closest distance found so far, and must only write to
+    /// the record when it reports a hit.
+    #[inline(always)]
+    pub fn hit<F>(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        rec: &mut HitRecord,
+        mut hit_primitive: F,
+    ) -> bool
+    where
+        F: FnMut(usize, f32, &mut HitRecord) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inv_dir = r.direction.recip();
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+        let mut stack = [0u32; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut node_index = 0usize;
+
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+
+        loop {
+            let node = &self.nodes[node_index];
+
+            if node.bounds.hit(r, inv_dir, t_min, closest_so_far) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for &index in &self.indices[first..first + node.count as usize] {
+                        if hit_primitive(index as usize, closest_so_far, rec) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                } else {
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, node_index + 1)
+                    } else {
+                        (node_index + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far as u32;
+                    stack_len += 1;
+                    node_index = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            node_index = stack[stack_len] as usize;
+        }
+
+        hit_anything
+    }
+
+    /// Returns as soon as any primitive reports a hit. `hit_primitive` is
+    /// called with the primitive index.
+    #[inline(always)]
+    pub fn occluded<F>(&self, r: Ray, t_min: f32, t_max: f32, mut hit_primitive: F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inv_dir = r.direction.recip();
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+        let mut stack = [0u32; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut node_index = 0usize;
+
+        loop {
+            let node = &self.nodes[node_index];
+
+            if node.bounds.hit(r, inv_dir, t_min, t_max) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for &index in &self.indices[first..first + node.count as usize] {
+                        if hit_primitive(index as usize) {
+                            return true;
+                        }
+                    }
+                } else {
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, node_index + 1)
+                    } else {
+                        (node_index + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far as u32;
+                    stack_len += 1;
+                    node_index = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                return false;
+            }
+            stack_len -= 1;
+            node_index = stack[stack_len] as usize;
+        }
+    }
+}
+
+fn make_leaf(node: &mut BvhNode, first: usize, count: usize, stats: &mut BvhStats) {
+    node.offset = first as u32;
+    node.count = count as u16;
+    stats.leaf_count += 1;
+    stats.max_leaf_size = usize::max(stats.max_leaf_size, count);
+}
+
+fn build_recursive(
+    primitives: &[BuildPrimitive],
+    indices: &mut [u32],
+    first: usize,
+    depth: usize,
+    nodes: &mut Vec<BvhNode>,
+    stats: &mut BvhStats,
+) {
+    let node_index = nodes.len();
+    let count = indices.len();
+    stats.max_depth = usize::max(stats.max_depth, depth);
+
+    let mut bounds = Aabb::EMPTY;
+    let mut centroid_bounds = Aabb::EMPTY;
+    for &i in indices.iter() {
+        let primitive = &primitives[i as usize];
+        bounds = bounds.union(primitive.bounds);
+        centroid_bounds = centroid_bounds.grow(primitive.centroid);
+    }
+
+    nodes.push(BvhNode {
+        bounds,
+        offset: 0,
+        count: 0,
+        axis: 0,
+    });
+
+    if count == 1 {
+        make_leaf(&mut nodes[node_index], first, count, stats);
+        return;
+    }
+
+    let axis = centroid_bounds.largest_axis();
+    let c_min = centroid_bounds.min[axis];
+    let c_max = centroid_bounds.max[axis];
+
+    let mid = if c_max - c_min <= f32::EPSILON || depth + 1 >= MAX_DEPTH / 2 {
+        // Centroids can't be told apart (or the tree is degenerating), so
+        // either keep them together or cut the range in half.
+        if count <= MAX_LEAF_SIZE {
+            make_leaf(&mut nodes[node_index], first, count, stats);
+            return;
+        }
+        count / 2
+    } else {
+        let scale = BIN_COUNT as f32 / (c_max - c_min);
+        let bin_index = |c: Vec3| usize::min(((c[axis] - c_min) * scale) as usize, BIN_COUNT - 1);
+
+        let mut bins = [Bin {
+            bounds: Aabb::EMPTY,
+            count: 0,
+        }; BIN_COUNT];
+
+        for &i in indices.iter() {
+            let primitive = &primitives[i as usize];
+            let bin = &mut bins[bin_index(primitive.centroid)];
+            bin.bounds = bin.bounds.union(primitive.bounds);
+            bin.count += 1;
+        }
+
+        // Sweep from the right to get the area and count of every right side,
+        // then from the left evaluating the cost of splitting after each bin.
+        let mut right_area = [0.0f32; BIN_COUNT];
+        let mut right_count = [0usize; BIN_COUNT];
+        let mut acc = Aabb::EMPTY;
+        let mut acc_count = 0;
+        for b in (1..BIN_COUNT).rev() {
+            acc = acc.union(bins[b].bounds);
+            acc_count += bins[b].count;
+            right_area[b] = acc.surface_area();
+            right_count[b] = acc_count;
+        }
+
+        let mut best_cost = f32::MAX;
+        let mut best_split = 0;
+        let mut acc = Aabb::EMPTY;
+        let mut acc_count = 0;
+        for b in 0..BIN_COUNT - 1 {
+            acc = acc.union(bins[b].bounds);
+            acc_count += bins[b].count;
+            let cost = acc.surface_area() * acc_count as f32
+                + right_area[b + 1] * right_count[b + 1] as f32;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = b;
+            }
+        }
+
+        let split_cost =
+            TRAVERSAL_COST + INTERSECTION_COST * best_cost / bounds.surface_area().max(1e-12);
+        let leaf_cost = INTERSECTION_COST * count as f32;
+
+        if count <= MAX_LEAF_SIZE && split_cost >= leaf_cost {
+            make_leaf(&mut nodes[node_index], first, count, stats);
+            return;
+        }
+
+        let mid = partition(indices, |i| {
+            bin_index(primitives[i as usize].centroid) <= best_split
+        });
+        if mid == 0 || mid == count {
+            count / 2
+        } else {
+            mid
+        }
+    };
+
+    let (left, right) = indices.split_at_mut(mid);
+    nodes[node_index].axis = axis as u16;
+    build_recursive(primitives, left, first, depth + 1, nodes, stats);
+    nodes[node_index].offset = nodes.len() as u32;
+    build_recursive(primitives, right, first + mid, depth + 1, nodes, stats);
+}
+
+/// Moves every element matching `pred` to the front, returning how many did.
+fn partition<F: Fn(u32) -> bool>(indices: &mut [u32], pred: F) -> usize {
+    let mut mid = 0;
+    for i in 0..indices.len() {
+        if pred(indices[i]) {
+            indices.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::*;
+    use crate::sphere::*;
+
+    fn random_spheres(rng: &mut Rng, count: usize) -> Vec<Sphere> {
+        (0..count)
+            .map(|i| {
+                let center = Vec3::random_range(rng, -10.0, 10.0);
+                Sphere::new(center, rng.randomf32_range(0.05, 1.5), i as u32)
+            })
+            .collect()
+    }
+
+    fn random_ray(rng: &mut Rng) -> Ray {
+        let origin = Vec3::random_range(rng, -15.0, 15.0);
+        Ray::new(origin, Vec3::random_unit_vector(rng))
+    }
+
+    #[test]
+    fn hit_matches_linear_scan() {
+        let mut rng = Rng::new(17);
+        let spheres = random_spheres(&mut rng, 500);
+        let bounds: Vec<Aabb> = spheres.iter().map(|s| s.bounding_box()).collect();
+        let (bvh, stats) = Bvh::build(&bounds);
+        assert_eq!(stats.primitive_count, spheres.len());
+
+        for _ in 0..2000 {
+            let r = random_ray(&mut rng);
+
+            let mut expected = HitRecord::new();
+            let mut expected_hit = false;
+            let mut closest_so_far = f32::MAX;
+            for sphere in &spheres {
+                if sphere.hit(r, 0.001, closest_so_far, &mut expected) {
+                    expected_hit = true;
+                    closest_so_far = expected.t;
+                }
+            }
+
+            let mut rec = HitRecord::new();
+            let hit = bvh.hit(r, 0.001, f32::MAX, &mut rec, |i, closest_so_far, rec| {
+                spheres[i].hit(r, 0.001, closest_so_far, rec)
+            });
+            assert_eq!(hit, expected_hit);
+            if hit {
+                assert_eq!(rec.t, expected.t);
+                assert_eq!(rec.material, expected.material);
+            }
+
+            let occluded = bvh.occluded(r, 0.001, f32::MAX, |i| {
+                spheres[i].occluded(r, 0.001, f32::MAX)
+            });
+            assert_eq!(occluded, expected_hit);
+        }
+    }
+
+    #[test]
+    fn empty_tree_hits_nothing() {
+        let (bvh, stats) = Bvh::build(&[]);
+        assert_eq!(stats.primitive_count, 0);
+        let r = Ray::new(Vec3::ZERO, vec3!(0, 0, 1));
+        let mut rec = HitRecord::new();
+        assert!(!bvh.hit(r, 0.001, f32::MAX, &mut rec, |_, _, _| true));
+        assert!(!bvh.occluded(r, 0.001, f32::MAX, |_| true));
+    }
+}
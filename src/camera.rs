@@ -1,3 +1,5 @@
+use crate::film::*;
+use crate::random::*;
 use crate::ray::*;
 use crate::vec3::*;
 
@@ -9,26 +11,66 @@ pub struct Camera {
     pub vertical: Vec3,
     pub u: Vec3,
     pub v: Vec3,
-    pub w: Vec3,
     pub lens_radius: f32,
 }
 
+/// Camera placement as described by a scene, turned into a `Camera` once
+/// the final image size is known.
+#[derive(Clone, Copy)]
+pub struct CameraSettings {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    /// Defaults to the distance between `lookfrom` and `lookat`.
+    pub focus_dist: Option<f32>,
+}
+
+impl CameraSettings {
+    pub fn build(&self, film: &Film) -> Camera {
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.lookfrom - self.lookat).len());
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            film,
+            self.aperture,
+            focus_dist,
+        )
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            lookfrom: vec3!(0, 0, 1),
+            lookat: vec3!(0, 0, 0),
+            vup: vec3!(0, 1, 0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: None,
+        }
+    }
+}
+
 impl Camera {
     pub fn new(
         lookfrom: Vec3,
         lookat: Vec3,
         vup: Vec3,
         vfov: f32,
-        aspect_ratio: f32,
+        film: &Film,
         aperture: f32,
         focus_dist: f32,
     ) -> Self {
         let theta = vfov.to_radians();
         let h = f32::tan(theta / 2.0);
         let viewport_height = 2.0 * h;
-        let viewport_width = viewport_height * aspect_ratio;
-
-        let focal_length = 1.0;
+        let viewport_width = viewport_height * film.aspect_ratio();
 
         let w = (lookfrom - lookat).normalized();
         let u = vup.cross(w).normalized();
@@ -48,14 +90,13 @@ impl Camera {
             vertical,
             u,
             v,
-            w,
             lens_radius,
         }
     }
 
     #[inline(always)]
-    pub fn get_ray(self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    pub fn get_ray(self, s: f32, t: f32, rng: &mut Rng) -> Ray {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
         let offset = self.u * rd.x + self.v * rd.y;
         Ray::new(
             self.origin + offset,
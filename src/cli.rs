@@ -0,0 +1,422 @@
+use crate::film::*;
+use crate::output::*;
+use crate::tonemap::*;
+use crate::RaytracerConfig;
+use std::path::PathBuf;
+
+pub const BUILTIN_RANDOM: &str = "builtin:random";
+
+const USAGE: &str = "\
+Usage: rust-raytracer [OPTIONS] [SCENE]
+
+Renders SCENE, a scene file or builtin:random (the default).
+
+Options:
+  -s, --scene <SCENE>      Same as the SCENE argument
+  -o, --output <PATH>      Output image [default: render.bmp]
+  -f, --format <FORMAT>    Output format (bmp, png, png16, exr, exr32, hdr, pfm),
+                           guessed from the extension by default
+  -r, --resolution <WxH>   Image size in pixels, e.g. 1920x1080
+      --width <N>          Image width in pixels
+      --height <N>         Image height in pixels
+      --crop <X0,X1,Y0,Y1> Only render this part of the image, given as fractions
+                           of the width and height, e.g. 0.25,0.75,0,0.5
+      --spp <N>            Samples per pixel
+      --bounces <N>        Maximum number of bounces per path
+  -t, --threads <N>        Render threads [default: available cores]
+      --tile <N>           Tile size in pixels
+      --seed <N>           Random seed
+      --tonemap <NAME>     Tone mapping operator for 8 and 16-bit output: linear,
+                           reinhard, reinhard-extended, aces, hable, agx
+      --exposure <EV>      Exposure adjustment in stops, applied before tone mapping
+      --white <VALUE>      White point of the tone mapping operator
+  -h, --help               Print this help
+
+Options given here take precedence over the settings in the scene file.";
+
+pub enum SceneSource {
+    BuiltinRandom,
+    File(PathBuf),
+}
+
+pub struct Options {
+    pub scene: SceneSource,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub crop: Option<[f32; 4]>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_bounces: Option<u32>,
+    pub max_threads: Option<u32>,
+    pub tile_size: Option<u32>,
+    pub seed: Option<u32>,
+    pub tone_map_operator: Option<ToneMapOperator>,
+    pub exposure: Option<f32>,
+    pub white_point: Option<f32>,
+}
+
+pub enum ParsedArgs {
+    Render(Options),
+    Help,
+}
+
+impl Options {
+    /// Overrides the scene's configuration with whatever was given on the
+    /// command line.
+    pub fn apply(&self, config: &mut RaytracerConfig) {
+        let overrides = [
+            (self.width, &mut config.film.width),
+            (self.height, &mut config.film.height),
+            (self.samples_per_pixel, &mut config.samples_per_pixel),
+            (self.max_bounces, &mut config.max_bounces),
+            (self.max_threads, &mut config.max_threads),
+            (self.tile_size, &mut config.tile_size),
+            (self.seed, &mut config.seed),
+        ];
+        for (value, field) in overrides {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        if let Some(crop) = self.crop {
+            config.film.crop = crop;
+        }
+        if let Some(operator) = self.tone_map_operator {
+            config.tone_mapping.operator = operator;
+        }
+        if let Some(exposure) = self.exposure {
+            config.tone_mapping.exposure = exposure;
+        }
+        if let Some(white_point) = self.white_point {
+            config.tone_mapping.white_point = Some(white_point);
+        }
+    }
+}
+
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+fn parse_count(option: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "{} expects a positive integer, got '{}'",
+            option, value
+        )),
+    }
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("--resolution expects WIDTHxHEIGHT, got '{}'", value);
+    let (w, h) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    match (w.parse::<u32>(), h.parse::<u32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_crop(value: &str) -> Result<[f32; 4], String> {
+    let invalid = || format!("--crop expects X0,X1,Y0,Y1 within [0, 1], got '{}'", value);
+    let values: Vec<f32> = value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    match values[..] {
+        [x0, x1, y0, y1] => Film::new(1, 1)
+            .with_crop_window(x0, x1, y0, y1)
+            .map(|film| film.crop)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the program arguments, not including the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<ParsedArgs, String> {
+    let mut args = args.into_iter();
+
+    let mut scene = None;
+    let mut output = None;
+    let mut format = None;
+    let mut options = Options {
+        scene: SceneSource::BuiltinRandom,
+        output: PathBuf::new(),
+        format: OutputFormat::Bmp,
+        width: None,
+        height: None,
+        crop: None,
+        samples_per_pixel: None,
+        max_bounces: None,
+        max_threads: None,
+        tile_size: None,
+        seed: None,
+        tone_map_operator: None,
+        exposure: None,
+        white_point: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if scene.is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            scene = Some(arg);
+            continue;
+        }
+
+        if arg == "-h" || arg == "--help" {
+            return Ok(ParsedArgs::Help);
+        }
+
+        // Both `--option value` and `--option=value` are accepted.
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) => (option.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || match inline_value.clone().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("{} expects a value", option)),
+        };
+
+        match option.as_str() {
+            "-s" | "--scene" => scene = Some(value()?),
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "-f" | "--format" => {
+                let name = value()?;
+                format = Some(
+                    OutputFormat::from_name(&name)
+                        .ok_or_else(|| format!("unknown output format '{}'", name))?,
+                );
+            }
+            "-r" | "--resolution" => {
+                let (w, h) = parse_resolution(&value()?)?;
+                options.width = Some(w);
+                options.height = Some(h);
+            }
+            "--crop" => options.crop = Some(parse_crop(&value()?)?),
+            "--width" => options.width = Some(parse_count(&option, &value()?)?),
+            "--height" => options.height = Some(parse_count(&option, &value()?)?),
+            "--spp" => options.samples_per_pixel = Some(parse_count(&option, &value()?)?),
+            "--bounces" => options.max_bounces = Some(parse_count(&option, &value()?)?),
+            "-t" | "--threads" => options.max_threads = Some(parse_count(&option, &value()?)?),
+            "--tile" => options.tile_size = Some(parse_count(&option, &value()?)?),
+            "--seed" => {
+                let seed = value()?;
+                options.seed = Some(
+                    seed.parse::<u32>()
+                        .map_err(|_| format!("--seed expects an integer, got '{}'", seed))?,
+                );
+            }
+            "--tonemap" => {
+                let name = value()?;
+                options.tone_map_operator =
+                    Some(ToneMapOperator::from_name(&name).ok_or_else(|| {
+                        format!(
+                            "unknown tone mapping operator '{}', expected one of {}",
+                            name,
+                            ToneMapOperator::NAMES
+                        )
+                    })?);
+            }
+            "--exposure" => {
+                let ev = value()?;
+                options.exposure = Some(
+                    ev.parse::<f32>()
+                        .ok()
+                        .filter(|ev| ev.is_finite())
+                        .ok_or_else(|| format!("--exposure expects a number, got '{}'", ev))?,
+                );
+            }
+            "--white" => {
+                let white = value()?;
+                options.white_point = Some(
+                    white
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|w| *w > 0.0 && w.is_finite())
+                        .ok_or_else(|| {
+                            format!("--white expects a positive number, got '{}'", white)
+                        })?,
+                );
+            }
+            _ => return Err(format!("unknown option '{}'", option)),
+        }
+    }
+
+    options.scene = match scene {
+        None => SceneSource::BuiltinRandom,
+        Some(s) if s == BUILTIN_RANDOM => SceneSource::BuiltinRandom,
+        Some(s) if s.starts_with("builtin:") => {
+            return Err(format!("unknown builtin scene '{}'", s));
+        }
+        Some(s) => SceneSource::File(PathBuf::from(s)),
+    };
+
+    options.output = output.unwrap_or_else(|| PathBuf::from("render.bmp"));
+    options.format = match format {
+        Some(format) => format,
+        None => OutputFormat::from_path(&options.output).ok_or_else(|| {
+            format!(
+                "cannot tell the output format of '{}', use --format",
+                options.output.display()
+            )
+        })?,
+    };
+
+    Ok(ParsedArgs::Render(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<ParsedArgs, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(ParsedArgs::Render(options)) => options,
+            Ok(ParsedArgs::Help) => panic!("unexpected --help for {:?}", args),
+            Err(e) => panic!("failed to parse {:?}: {}", args, e),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error for {:?}", args),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let o = options(&[]);
+        assert!(matches!(o.scene, SceneSource::BuiltinRandom));
+        assert_eq!(o.output, PathBuf::from("render.bmp"));
+        assert!(matches!(o.format, OutputFormat::Bmp));
+        assert_eq!(o.width, None);
+        assert_eq!(o.seed, None);
+    }
+
+    #[test]
+    fn scene_output_and_format() {
+        let o = options(&["scenes/cornell.scene", "-o", "out.exr"]);
+        assert!(
+            matches!(o.scene, SceneSource::File(ref p) if p == Path::new("scenes/cornell.scene"))
+        );
+        assert!(matches!(o.format, OutputFormat::Exr));
+
+        let o = options(&["--scene=a.scene", "--output=out.png", "--format", "png16"]);
+        assert!(matches!(o.scene, SceneSource::File(ref p) if p == Path::new("a.scene")));
+        assert!(matches!(o.format, OutputFormat::Png16));
+
+        assert!(matches!(
+            options(&["builtin:random"]).scene,
+            SceneSource::BuiltinRandom
+        ));
+    }
+
+    #[test]
+    fn numeric_options() {
+        let o = options(&[
+            "-r",
+            "640x480",
+            "--spp",
+            "16",
+            "--bounces=4",
+            "-t",
+            "2",
+            "--tile",
+            "32",
+            "--seed",
+            "0",
+            "--exposure",
+            "-1.5",
+            "--white",
+            "4",
+        ]);
+        assert_eq!((o.width, o.height), (Some(640), Some(480)));
+        assert_eq!(o.samples_per_pixel, Some(16));
+        assert_eq!(o.max_bounces, Some(4));
+        assert_eq!(o.max_threads, Some(2));
+        assert_eq!(o.tile_size, Some(32));
+        assert_eq!(o.seed, Some(0));
+        assert_eq!(o.exposure, Some(-1.5));
+        assert_eq!(o.white_point, Some(4.0));
+
+        let o = options(&["--width", "10", "--crop", "0.25,0.75,0,0.5"]);
+        assert_eq!(o.width, Some(10));
+        assert_eq!(o.crop, Some([0.25, 0.75, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn help() {
+        assert!(matches!(
+            parse(&["a.scene", "--help"]),
+            Ok(ParsedArgs::Help)
+        ));
+        assert!(matches!(parse(&["-h"]), Ok(ParsedArgs::Help)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error(&["--spp", "0"]),
+            "--spp expects a positive integer, got '0'"
+        );
+        assert_eq!(error(&["--spp"]), "--spp expects a value");
+        assert_eq!(
+            error(&["--seed", "-1"]),
+            "--seed expects an integer, got '-1'"
+        );
+        assert_eq!(
+            error(&["-r", "640"]),
+            "--resolution expects WIDTHxHEIGHT, got '640'"
+        );
+        assert_eq!(
+            error(&["-r", "0x480"]),
+            "--resolution expects WIDTHxHEIGHT, got '0x480'"
+        );
+        assert!(error(&["--crop", "0.5,0.25,0,1"]).starts_with("--crop expects"));
+        assert!(error(&["--crop", "0,1,0"]).starts_with("--crop expects"));
+        assert_eq!(
+            error(&["--white", "0"]),
+            "--white expects a positive number, got '0'"
+        );
+        assert_eq!(
+            error(&["--exposure", "inf"]),
+            "--exposure expects a number, got 'inf'"
+        );
+        assert!(
+            error(&["--tonemap", "filmic"]).starts_with("unknown tone mapping operator 'filmic'")
+        );
+        assert_eq!(error(&["-f", "gif"]), "unknown output format 'gif'");
+        assert_eq!(
+            error(&["-o", "out.gif"]),
+            "cannot tell the output format of 'out.gif', use --format"
+        );
+        assert_eq!(error(&["--fast"]), "unknown option '--fast'");
+        assert_eq!(
+            error(&["a.scene", "b.scene"]),
+            "unexpected argument 'b.scene'"
+        );
+        assert_eq!(
+            error(&["builtin:cornell"]),
+            "unknown builtin scene 'builtin:cornell'"
+        );
+    }
+
+    #[test]
+    fn apply_overrides_only_given_options() {
+        let mut config = RaytracerConfig::default();
+        let spp = config.samples_per_pixel;
+        options(&["--width", "320", "--seed", "9"]).apply(&mut config);
+        assert_eq!(config.film.width, 320);
+        assert_eq!(config.seed, 9);
+        assert_eq!(config.samples_per_pixel, spp);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::quad::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+/// Axis-aligned box made of six quads facing outwards. (Named so it doesn't
+/// shadow `std::boxed::Box`.) The sides are the box's primitives, in the
+/// order front, right, back, left, top and bottom, each with its own UVs.
+#[derive(Clone)]
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: MaterialId,
+    pub sides: [Quad; 6],
+}
+
+impl Cuboid {
+    pub fn new(a: Vec3, b: Vec3, material: MaterialId) -> Self {
+        let min = Vec3::min(a, b);
+        let max = Vec3::max(a, b);
+        let dx = vec3!(max.x - min.x, 0, 0);
+        let dy = vec3!(0, max.y - min.y, 0);
+        let dz = vec3!(0, 0, max.z - min.z);
+
+        let side = |q: Vec3, u: Vec3, v: Vec3| Quad::new(q, u, v, material);
+        let sides = [
+            side(vec3!(min.x, min.y, max.z), dx, dy),
+            side(vec3!(max.x, min.y, max.z), -dz, dy),
+            side(vec3!(max.x, min.y, min.z), -dx, dy),
+            side(vec3!(min.x, min.y, min.z), dz, dy),
+            side(vec3!(min.x, max.y, max.z), dx, -dz),
+            side(vec3!(min.x, min.y, min.z), dx, dz),
+        ];
+
+        Self {
+            min,
+            max,
+            material,
+            sides,
+        }
+    }
+
+    /// Like `hit`, with the sides cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        for (i, side) in self.sides.iter().enumerate() {
+            if side.hit_primitive(r, t_min, closest_so_far, alpha, rec, i as u32) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+}
+
+impl Hittable for Cuboid {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+
+    #[inline(always)]
+    fn occluded(&self, r: Ray, t_min: f32, t_max: f32) -> bool {
+        let mut rec = HitRecord::new();
+        self.sides
+            .iter()
+            .any(|side| side.hit(r, t_min, t_max, &mut rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sides_face_outwards() {
+        let cuboid = Cuboid::new(vec3!(1, 2, 3), vec3!(-1, -2, -3), 0);
+        let outward = [
+            vec3!(0, 0, 1),
+            vec3!(1, 0, 0),
+            vec3!(0, 0, -1),
+            vec3!(-1, 0, 0),
+            vec3!(0, 1, 0),
+            vec3!(0, -1, 0),
+        ];
+        for (i, (side, n)) in cuboid.sides.iter().zip(outward).enumerate() {
+            assert!(side.u.cross(side.v).normalized() == n);
+
+            // A ray from outside hits the front of this side first.
+            let mut rec = HitRecord::new();
+            let target = Vec3::hadamard(n, cuboid.max);
+            let r = Ray::new(target + 10.0 * n, -n);
+            assert!(cuboid.hit(r, 0.001, f32::MAX, &mut rec));
+            assert_eq!(rec.primitive, i as u32);
+            assert!(rec.front_face && rec.normal == n);
+            assert!((rec.p - target).len() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn misses_around_the_box() {
+        let cuboid = Cuboid::new(vec3!(0, 0, 0), vec3!(1, 1, 1), 0);
+        let mut rec = HitRecord::new();
+        for origin in [vec3!(1.5, 0.5, 5), vec3!(0.5, -0.5, 5), vec3!(-0.1, 1.1, 5)] {
+            let r = Ray::new(origin, vec3!(0, 0, -1));
+            assert!(!cuboid.hit(r, 0.001, f32::MAX, &mut rec));
+            assert!(!cuboid.occluded(r, 0.001, f32::MAX));
+        }
+        // From inside, the far side is hit from behind.
+        let r = Ray::new(vec3!(0.5, 0.5, 0.5), vec3!(1, 0, 0));
+        assert!(cuboid.hit(r, 0.001, f32::MAX, &mut rec));
+        assert!(!rec.front_face && rec.normal == vec3!(-1, 0, 0));
+    }
+}
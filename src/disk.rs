@@ -0,0 +1,137 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+
+/// Flat disk facing `normal` (normalized). u is the angle around the center
+/// and v the distance from it, both from 0 to 1.
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: MaterialId,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: MaterialId) -> Self {
+        Self {
+            center,
+            normal: normal.normalized(),
+            radius,
+            material,
+        }
+    }
+
+    /// Like `hit`, with the surface cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let denom = self.normal.dot(r.direction);
+        if denom == 0.0 {
+            return false;
+        }
+        let t = self.normal.dot(self.center - r.origin) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+        let p = r.at(t);
+        let d = p - self.center;
+        if d.lensq() > self.radius * self.radius {
+            return false;
+        }
+
+        record_hit(alpha, r, rec, |rec| {
+            let (s, t_) = self.normal.orthonormal_basis();
+            let (x, y) = (d.dot(s), d.dot(t_));
+            let distance = f32::sqrt(x * x + y * y);
+            let phi = f32::atan2(y, x).rem_euclid(2.0 * PI);
+
+            rec.t = t;
+            rec.p = p;
+            rec.set_face_normal(r, self.normal);
+            rec.u = phi / (2.0 * PI);
+            rec.v = distance / self.radius;
+            // Undefined at the center, where the shading frame falls back to
+            // an arbitrary tangent.
+            rec.dpdu = (2.0 * PI) * (x * t_ - y * s);
+            rec.dpdv = if distance > 0.0 {
+                (self.radius / distance) * d
+            } else {
+                Vec3::ZERO
+            };
+            rec.material = self.material;
+            rec.primitive = 0;
+        })
+    }
+}
+
+impl Hittable for Disk {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        // Extent of the rim along each axis.
+        let n = self.normal;
+        let e = self.radius
+            * vec3!(
+                f32::sqrt(f32::max(0.0, 1.0 - n.x * n.x)),
+                f32::sqrt(f32::max(0.0, 1.0 - n.y * n.y)),
+                f32::sqrt(f32::max(0.0, 1.0 - n.z * n.z))
+            );
+        Aabb::new(self.center - e, self.center + e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_inside_and_misses_outside() {
+        let disk = Disk::new(vec3!(1, 2, 3), vec3!(0, 2, 0), 0.5, 0);
+        let mut rec = HitRecord::new();
+
+        let r = Ray::new(vec3!(1.3, 5, 3), vec3!(0, -1, 0));
+        assert!(disk.hit(r, 0.001, f32::MAX, &mut rec));
+        assert!((rec.t - 3.0).abs() < 1e-6);
+        assert!(rec.front_face && rec.normal == vec3!(0, 1, 0));
+        assert!((rec.v - 0.6).abs() < 1e-5);
+
+        let r = Ray::new(vec3!(1, 0, 3.2), vec3!(0, 1, 0));
+        assert!(disk.hit(r, 0.001, f32::MAX, &mut rec));
+        assert!(!rec.front_face && rec.normal == vec3!(0, -1, 0));
+
+        // Just outside the rim, and parallel to the disk.
+        let r = Ray::new(vec3!(1.36, 5, 3.36), vec3!(0, -1, 0));
+        assert!(!disk.hit(r, 0.001, f32::MAX, &mut rec));
+        let r = Ray::new(vec3!(0, 2, 3), vec3!(1, 0, 0));
+        assert!(!disk.hit(r, 0.001, f32::MAX, &mut rec));
+    }
+
+    #[test]
+    fn bounds_contain_the_rim() {
+        let disk = Disk::new(vec3!(0, 0, 0), vec3!(1, 1, 0), 2.0, 0);
+        let bounds = disk.bounding_box();
+        let (s, t) = disk.normal.orthonormal_basis();
+        for i in 0..64 {
+            let phi = i as f32 / 64.0 * 2.0 * PI;
+            let p = disk.radius * (phi.cos() * s + phi.sin() * t);
+            for axis in 0..3 {
+                assert!(bounds.min[axis] - 1e-5 <= p[axis] && p[axis] <= bounds.max[axis] + 1e-5);
+            }
+        }
+    }
+}
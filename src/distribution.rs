@@ -0,0 +1,147 @@
+/// Piecewise constant 1D distribution over `[0, 1)`, proportional to a
+/// tabulated function.
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    func_int: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f32]) -> Self {
+        let n = func.len();
+        let func: Vec<f32> = func.iter().map(|f| f.abs()).collect();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            // Nothing to prefer: fall back to a uniform distribution.
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        } else {
+            for c in cdf.iter_mut() {
+                *c /= func_int;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+
+    #[inline(always)]
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    #[inline(always)]
+    pub fn integral(&self) -> f32 {
+        self.func_int
+    }
+
+    /// Maps a uniform `u` to a point in `[0, 1)`. Returns the point, its
+    /// density and the index of the segment it falls in.
+    #[inline(always)]
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        // Last cdf entry not greater than u.
+        let offset = self.cdf.partition_point(|&c| c <= u).clamp(1, self.count()) - 1;
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let pdf = self.pdf_at(offset);
+        let x = (offset as f32 + du) / self.count() as f32;
+        (x.min(1.0 - f32::EPSILON), pdf, offset)
+    }
+
+    /// Density of the segment `offset`.
+    #[inline(always)]
+    pub fn pdf_at(&self, offset: usize) -> f32 {
+        if self.func_int > 0.0 {
+            self.func[offset] / self.func_int
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise constant 2D distribution over `[0, 1)^2`: a marginal
+/// distribution over rows and one conditional distribution per row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `height` rows of `width` values.
+    pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks_exact(width)
+            .take(height)
+            .map(Distribution1D::new)
+            .collect();
+        let row_integrals: Vec<f32> = conditional.iter().map(|d| d.integral()).collect();
+
+        Self {
+            conditional,
+            marginal: Distribution1D::new(&row_integrals),
+        }
+    }
+
+    /// Returns the sampled `(u, v)` point and its density.
+    #[inline(always)]
+    pub fn sample(&self, u0: f32, u1: f32) -> ([f32; 2], f32) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        ([u, v], pdf_u * pdf_v)
+    }
+
+    #[inline(always)]
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let row = ((v * self.marginal.count() as f32) as usize).min(self.marginal.count() - 1);
+        let conditional = &self.conditional[row];
+        let column = ((u * conditional.count() as f32) as usize).min(conditional.count() - 1);
+        conditional.pdf_at(column) * self.marginal.pdf_at(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_2d_reproduces_pdf() {
+        let func = [1.0, 2.0, 3.0, 4.0];
+        let distribution = Distribution2D::new(&func, 2, 2);
+
+        // Stratified uniforms, so the histogram converges quickly.
+        let n = 64;
+        let mut histogram = [0; 4];
+        for i in 0..n {
+            for j in 0..n {
+                let u0 = (i as f32 + 0.5) / n as f32;
+                let u1 = (j as f32 + 0.5) / n as f32;
+                let ([u, v], pdf) = distribution.sample(u0, u1);
+                assert!(pdf == distribution.pdf(u, v));
+                histogram[(v * 2.0) as usize * 2 + (u * 2.0) as usize] += 1;
+            }
+        }
+
+        let total: f32 = func.iter().sum();
+        for (k, (count, f)) in histogram.iter().zip(func).enumerate() {
+            let fraction = *count as f32 / (n * n) as f32;
+            assert!((fraction - f / total).abs() < 0.01);
+            // Each cell has area 1/4, so its density is 4 times its share.
+            let (u, v) = ((k % 2) as f32 * 0.5 + 0.25, (k / 2) as f32 * 0.5 + 0.25);
+            assert!((distribution.pdf(u, v) - 4.0 * f / total).abs() < 1e-6);
+        }
+    }
+}
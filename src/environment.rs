@@ -0,0 +1,124 @@
+use crate::distribution::*;
+use crate::image::*;
+use crate::random::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+
+/// Distant light from an equirectangular (latitude-longitude) image. The
+/// image center looks down -z with +y up; `rotation` turns it around the
+/// y axis.
+pub struct Environment {
+    image: ImageBuffer,
+    intensity: f32,
+    /// Rotation around +y, in radians.
+    rotation: f32,
+    distribution: Distribution2D,
+}
+
+impl Environment {
+    pub fn new(image: ImageBuffer, intensity: f32, rotation_degrees: f32) -> Self {
+        let (w, h) = (image.width as usize, image.height as usize);
+
+        // Sample proportionally to luminance, weighted by the solid angle
+        // of each row, which shrinks towards the poles.
+        let mut func = Vec::with_capacity(w * h);
+        for y in 0..h {
+            let sin_theta = (PI * (y as f32 + 0.5) / h as f32).sin();
+            for x in 0..w {
+                let c = image.get(x as u32, y as u32);
+                let luminance = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+                func.push(luminance.max(0.0) * sin_theta);
+            }
+        }
+
+        Self {
+            distribution: Distribution2D::new(&func, w, h),
+            image,
+            intensity,
+            rotation: rotation_degrees.to_radians(),
+        }
+    }
+
+    #[inline(always)]
+    fn direction_to_uv(&self, d: Vec3) -> [f32; 2] {
+        let d = d.normalized();
+        let phi = f32::atan2(d.x, -d.z) - self.rotation;
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        // atan2 rather than acos keeps the angle accurate near the poles.
+        let v = f32::atan2(d.x.hypot(d.z), d.y) / PI;
+        [u, v]
+    }
+
+    #[inline(always)]
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        let sin_theta = theta.sin();
+        vec3!(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos())
+    }
+
+    /// Nearest texel: the sampling distribution is piecewise constant over
+    /// texels, so this keeps the two consistent.
+    #[inline(always)]
+    fn lookup(&self, u: f32, v: f32) -> Color {
+        let x = ((u * self.image.width as f32) as u32).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as u32).min(self.image.height - 1);
+        self.image.get(x, y) * self.intensity
+    }
+
+    #[inline(always)]
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let [u, v] = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    /// Returns a unit direction, the radiance arriving from it and its solid
+    /// angle density.
+    #[inline(always)]
+    pub fn sample(&self, rng: &mut Rng) -> Option<(Vec3, Color, f32)> {
+        let ([u, v], pdf_uv) = self.distribution.sample(rng.randomf32(), rng.randomf32());
+        let sin_theta = (v * PI).sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let pdf = pdf_uv / (2.0 * PI * PI * sin_theta);
+        Some((self.uv_to_direction(u, v), self.lookup(u, v), pdf))
+    }
+
+    #[inline(always)]
+    pub fn pdf(&self, direction: Vec3) -> f32 {
+        let [u, v] = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_matches_sample() {
+        let mut rng = Rng::new(11);
+        let mut image = ImageBuffer::new(8, 4);
+        for c in image.pixels.iter_mut() {
+            *c = Vec3::random_range(&mut rng, 0.0, 4.0);
+        }
+        let environment = Environment::new(image, 1.5, 30.0);
+
+        for _ in 0..1000 {
+            let (wi, radiance, pdf) = environment.sample(&mut rng).unwrap();
+            let expected = environment.pdf(wi);
+            assert!(
+                (pdf - expected).abs() <= 1e-3 * pdf,
+                "{} != {}",
+                pdf,
+                expected
+            );
+            assert!(radiance == environment.radiance(wi));
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/// Image the camera renders into: its full resolution plus an optional crop
+/// window restricting rendering (and the output image) to part of it.
+///
+/// Pixel coordinates are raster coordinates, with (0, 0) at the top left.
+#[derive(Clone, Copy)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    /// Crop window as fractions of the full image: `[x0, x1, y0, y1]`.
+    pub crop: [f32; 4],
+}
+
+/// Pixel rectangle, `x1` and `y1` exclusive.
+#[derive(Clone, Copy)]
+pub struct PixelBounds {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl PixelBounds {
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+}
+
+impl Film {
+    /// Panics if either dimension is zero.
+    pub fn new(width: u32, height: u32) -> Self {
+        assert!(
+            width > 0 && height > 0,
+            "film size must be positive, got {}x{}",
+            width,
+            height
+        );
+        Self {
+            width,
+            height,
+            crop: [0.0, 1.0, 0.0, 1.0],
+        }
+    }
+
+    /// Returns `None` if the window is empty or outside of `[0, 1]`.
+    pub fn with_crop_window(mut self, x0: f32, x1: f32, y0: f32, y1: f32) -> Option<Self> {
+        let valid = |a: f32, b: f32| (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b) && a < b;
+        if !valid(x0, x1) || !valid(y0, y1) {
+            return None;
+        }
+        self.crop = [x0, x1, y0, y1];
+        Some(self)
+    }
+
+    #[inline(always)]
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// Pixels covered by the crop window. Always at least one pixel wide.
+    pub fn pixel_bounds(&self) -> PixelBounds {
+        let [x0, x1, y0, y1] = self.crop;
+        let w = self.width as f32;
+        let h = self.height as f32;
+        let x0 = u32::min((x0 * w).floor() as u32, self.width - 1);
+        let y0 = u32::min((y0 * h).floor() as u32, self.height - 1);
+        PixelBounds {
+            x0,
+            y0,
+            x1: ((x1 * w).ceil() as u32).clamp(x0 + 1, self.width),
+            y1: ((y1 * h).ceil() as u32).clamp(y0 + 1, self.height),
+        }
+    }
+}
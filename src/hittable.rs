@@ -1,18 +1,45 @@
+use crate::aabb::*;
 use crate::material::*;
+use crate::microfacet::*;
 use crate::ray::*;
 use crate::vec3::*;
 
 #[derive(Clone, Copy)]
 pub struct HitRecord {
     pub p: Vec3,
+    /// Shading normal, which materials work with. It can differ from the
+    /// geometric normal through interpolated vertex normals or normal maps,
+    /// but is always on the same side of the surface.
     pub normal: Vec3,
-    pub material: Material,
+    /// True normal of the surface, facing against the ray like `normal`.
+    pub geometric_normal: Vec3,
+    /// Partial derivatives of the hit point with respect to u and v. `dpdu`
+    /// orients the shading frame's first tangent.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub material: MaterialId,
     pub t: f32,
+    pub u: f32,
+    pub v: f32,
     pub front_face: bool,
+    /// Index of the object in the `HittableList` that was hit.
+    pub object: u32,
+    /// Index of the primitive within that object: the face of a mesh, zero
+    /// for everything else.
+    pub primitive: u32,
 }
 
 pub trait Hittable {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool;
+    fn bounding_box(&self) -> Aabb;
+
+    /// Whether anything is hit between `t_min` and `t_max`. Used for shadow
+    /// rays, which need any hit rather than the closest one.
+    #[inline(always)]
+    fn occluded(&self, r: Ray, t_min: f32, t_max: f32) -> bool {
+        let mut rec = HitRecord::new();
+        self.hit(r, t_min, t_max, &mut rec)
+    }
 }
 
 impl HitRecord {
@@ -21,9 +48,16 @@ impl HitRecord {
         Self {
             p: vec3!(0.0),
             normal: vec3!(0.0),
+            geometric_normal: vec3!(0.0),
+            dpdu: vec3!(0.0),
+            dpdv: vec3!(0.0),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
-            material: Material::new(MaterialType::Lambertian, color!(), 0.0),
+            object: 0,
+            primitive: 0,
+            material: 0,
         }
     }
 
@@ -35,5 +69,24 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    /// Local frame around the shading normal, with its first tangent along
+    /// `dpdu` where that is defined.
+    #[inline(always)]
+    pub fn shading_frame(&self) -> Frame {
+        Frame::from_tangent(self.normal, self.dpdu)
+    }
+
+    /// Whether `wo` and `wi` are on the same side of the true surface exactly
+    /// when they are on the same side of the shading normal. Paths that break
+    /// this would let light leak through the surface, or reflect off it from
+    /// below, so the integrator drops them.
+    #[inline(always)]
+    pub fn is_consistent(&self, wo: Vec3, wi: Vec3) -> bool {
+        let geometric = wo.dot(self.geometric_normal) * wi.dot(self.geometric_normal) > 0.0;
+        let shading = wo.dot(self.normal) * wi.dot(self.normal) > 0.0;
+        geometric == shading
     }
 }
@@ -1,39 +1,322 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::cuboid::*;
+use crate::disk::*;
+use crate::environment::*;
 use crate::hittable::*;
+use crate::light::*;
+use crate::material::*;
+use crate::plane::*;
+use crate::quad::*;
+use crate::random::*;
 use crate::ray::*;
+use crate::shape::*;
 use crate::sphere::*;
+use crate::triangle::*;
+use crate::triangle_mesh::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+const NO_LIGHT: u32 = u32::MAX;
 
 pub struct HittableList {
-    pub spheres: Vec<Sphere>,
+    pub objects: Vec<Shape>,
+    pub materials: MaterialTable,
+    bvh: Bvh,
+    /// Index into `objects` of every primitive of the BVH.
+    bounded: Vec<u32>,
+    /// Objects without bounds (planes), tested against every ray.
+    unbounded: Vec<u32>,
+    lights: Vec<Light>,
+    /// Index of the first light of each object, `NO_LIGHT` for objects that
+    /// don't emit. Meshes own one light per face.
+    light_offsets: Vec<u32>,
+    /// The environment, if it is one of the lights.
+    environment: Option<Arc<Environment>>,
+    /// Directional lights with a sun disk that escaping rays can see.
+    sun_disk_lights: Vec<usize>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
-        Self { spheres: vec![] }
+        Self {
+            objects: vec![],
+            materials: MaterialTable::new(),
+            bvh: Bvh::new(),
+            bounded: vec![],
+            unbounded: vec![],
+            lights: vec![],
+            light_offsets: vec![],
+            environment: None,
+            sun_disk_lights: vec![],
+        }
     }
 
+    /// Empties the list, as if it was just created.
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
-        self.spheres.clear();
+        *self = Self::new();
+    }
+
+    pub fn add_material(&mut self, material: Box<dyn Material>) -> MaterialId {
+        self.materials.add(material)
     }
 
     pub fn add_sphere(&mut self, sphere: Sphere) {
-        self.spheres.push(sphere);
+        self.objects.push(Shape::Sphere(sphere));
+    }
+
+    pub fn add_triangle(&mut self, triangle: Triangle) {
+        self.objects.push(Shape::Triangle(triangle));
+    }
+
+    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
+        self.objects.push(Shape::Mesh(mesh));
+    }
+
+    pub fn add_quad(&mut self, quad: Quad) {
+        self.objects.push(Shape::Quad(quad));
+    }
+
+    pub fn add_disk(&mut self, disk: Disk) {
+        self.objects.push(Shape::Disk(disk));
+    }
+
+    pub fn add_plane(&mut self, plane: Plane) {
+        self.objects.push(Shape::Plane(plane));
+    }
+
+    pub fn add_cuboid(&mut self, cuboid: Cuboid) {
+        self.objects.push(Shape::Cuboid(cuboid));
+    }
+
+    /// Builds the acceleration structure used by `hit`. Must be called again
+    /// after objects are added. Meshes carry their own BVH, so this one only
+    /// sees them as a single object; planes stay outside of it.
+    pub fn build_bvh(&mut self) -> BvhStats {
+        let (bounded, unbounded): (Vec<u32>, Vec<u32>) =
+            (0..self.objects.len() as u32).partition(|&i| self.objects[i as usize].is_bounded());
+        let bounds: Vec<Aabb> = bounded
+            .iter()
+            .map(|&i| self.objects[i as usize].bounding_box())
+            .collect();
+        let (bvh, stats) = Bvh::build(&bounds);
+        self.bvh = bvh;
+        self.bounded = bounded;
+        self.unbounded = unbounded;
+        stats
+    }
+
+    /// Collects the emissive objects, followed by the scene's `lights`, into
+    /// the light list used by `sample_light`. Must be called again after
+    /// objects are added.
+    pub fn build_lights(&mut self, lights: &[Light]) {
+        self.lights.clear();
+        self.light_offsets.clear();
+
+        for object in &self.objects {
+            let emission = self.materials.get(object.material()).emitted();
+            let offset = self.lights.len() as u32;
+            match object {
+                Shape::Sphere(s) if !emission.near_zero() => {
+                    self.lights.push(Light::Sphere {
+                        center: s.center,
+                        radius: s.radius,
+                        emission,
+                    });
+                }
+                Shape::Triangle(t) if !emission.near_zero() => {
+                    self.lights.push(Light::Triangle {
+                        v0: t.v0,
+                        v1: t.v1,
+                        v2: t.v2,
+                        emission,
+                    });
+                }
+                Shape::Mesh(m) if !emission.near_zero() => {
+                    for &[i0, i1, i2] in &m.indices {
+                        self.lights.push(Light::Triangle {
+                            v0: m.positions[i0 as usize],
+                            v1: m.positions[i1 as usize],
+                            v2: m.positions[i2 as usize],
+                            emission,
+                        });
+                    }
+                }
+                Shape::Quad(q) if !emission.near_zero() => {
+                    self.lights.push(Light::Quad {
+                        q: q.q,
+                        u: q.u,
+                        v: q.v,
+                        emission,
+                    });
+                }
+                Shape::Disk(d) if !emission.near_zero() => {
+                    self.lights.push(Light::Disk {
+                        center: d.center,
+                        normal: d.normal,
+                        radius: d.radius,
+                        emission,
+                    });
+                }
+                Shape::Cuboid(c) if !emission.near_zero() => {
+                    for side in &c.sides {
+                        self.lights.push(Light::Quad {
+                            q: side.q,
+                            u: side.u,
+                            v: side.v,
+                            emission,
+                        });
+                    }
+                }
+                _ => {
+                    self.light_offsets.push(NO_LIGHT);
+                    continue;
+                }
+            }
+            self.light_offsets.push(offset);
+        }
+
+        self.lights.extend_from_slice(lights);
+        self.environment = self.lights.iter().find_map(|light| match light {
+            Light::Environment(environment) => Some(Arc::clone(environment)),
+            _ => None,
+        });
+        self.sun_disk_lights = (0..self.lights.len())
+            .filter(|&i| {
+                matches!(self.lights[i], Light::Directional { cos_angular_radius, .. }
+                    if cos_angular_radius < 1.0)
+            })
+            .collect();
+    }
+
+    /// Picks a light uniformly and samples a direction towards it from `p`.
+    /// The returned density includes the probability of picking the light.
+    #[inline(always)]
+    pub fn sample_light(&self, p: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let count = self.lights.len();
+        let index = usize::min((rng.randomf32() * count as f32) as usize, count - 1);
+        let mut sample = self.lights[index].sample(p, rng)?;
+        sample.pdf /= count as f32;
+        Some(sample)
+    }
+
+    /// Density with which `sample_light` would have produced the direction
+    /// from `p` to the emitter hit recorded in `rec`.
+    #[inline(always)]
+    pub fn light_pdf(&self, p: Vec3, rec: &HitRecord) -> f32 {
+        let offset = self.light_offsets[rec.object as usize];
+        if offset == NO_LIGHT {
+            return 0.0;
+        }
+        let light = &self.lights[(offset + rec.primitive) as usize];
+        light.pdf(p, rec.p, rec.geometric_normal) / self.lights.len() as f32
+    }
+
+    /// Density with which `sample_light` would have picked `direction`
+    /// towards the environment, zero if it isn't sampled.
+    #[inline(always)]
+    pub fn environment_pdf(&self, direction: Vec3) -> f32 {
+        match &self.environment {
+            Some(environment) => environment.pdf(direction) / self.lights.len() as f32,
+            None => 0.0,
+        }
+    }
+
+    /// Radiance of the directional lights' sun disks seen along `direction`, and
+    /// the density with which `sample_light` would have picked it.
+    #[inline(always)]
+    pub fn sun_disk_radiance(&self, direction: Vec3) -> (Color, f32) {
+        let direction = direction.normalized();
+        let mut radiance = Color::ZERO;
+        let mut pdf = 0.0;
+        for &i in &self.sun_disk_lights {
+            if let Some((l, p)) = self.lights[i].sun_disk_radiance(direction) {
+                radiance += l;
+                pdf += p;
+            }
+        }
+        (radiance, pdf / self.lights.len().max(1) as f32)
     }
 }
 
 impl Hittable for HittableList {
     #[inline(always)]
     fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        let mut temp_rec = HitRecord::new();
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
-
-        for sphere in &self.spheres {
-            if sphere.hit(r, t_min, closest_so_far, &mut temp_rec) {
+        for &i in &self.unbounded {
+            let object = &self.objects[i as usize];
+            let alpha = self.materials.alpha(object.material());
+            if object.hit(r, t_min, closest_so_far, alpha, rec) {
+                rec.object = i;
                 hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec;
+                closest_so_far = rec.t;
             }
         }
-        hit_anything
+
+        self.bvh
+            .hit(r, t_min, closest_so_far, rec, |i, closest_so_far, rec| {
+                let i = self.bounded[i];
+                let object = &self.objects[i as usize];
+                let alpha = self.materials.alpha(object.material());
+                if object.hit(r, t_min, closest_so_far, alpha, rec) {
+                    rec.object = i;
+                    return true;
+                }
+                false
+            })
+            || hit_anything
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    #[inline(always)]
+    fn occluded(&self, r: Ray, t_min: f32, t_max: f32) -> bool {
+        let occluded = |i: u32| {
+            let object = &self.objects[i as usize];
+            object.occluded(r, t_min, t_max, self.materials.alpha(object.material()))
+        };
+        self.unbounded.iter().any(|&i| occluded(i))
+            || self
+                .bvh
+                .occluded(r, t_min, t_max, |i| occluded(self.bounded[i]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::*;
+    use crate::texture::*;
+
+    #[test]
+    fn alpha_applies_to_shapes_added_before_it() {
+        let mut world = HittableList::new();
+        let material = world.add_material(Box::new(Lambertian::new(color!(0.5))));
+        world.add_quad(Quad::new(
+            vec3!(-1, -1, 0),
+            vec3!(2, 0, 0),
+            vec3!(0, 2, 0),
+            material,
+        ));
+        world.add_cuboid(Cuboid::new(vec3!(-1, -1, -3), vec3!(1, 1, -2), material));
+        world.build_bvh();
+
+        let r = Ray::new(vec3!(0, 0, 1), vec3!(0, 0, -1));
+        assert!(world.hit(r, 0.001, f32::MAX, &mut HitRecord::new()));
+        assert!(world.occluded(r, 0.001, f32::MAX));
+
+        let transparent = Arc::new(SolidColor::new(color!(0.0)));
+        let mask = AlphaMask::new(transparent, AlphaMode::Threshold, 0.5);
+        world.materials.set_alpha(material, mask).unwrap();
+        assert!(!world.hit(r, 0.001, f32::MAX, &mut HitRecord::new()));
+        assert!(!world.occluded(r, 0.001, f32::MAX));
     }
 }
@@ -0,0 +1,440 @@
+use crate::vec3::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Linear RGB image, rows top to bottom.
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+impl ImageBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::ZERO; width as usize * height as usize],
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    #[inline(always)]
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[y as usize * self.width as usize + x as usize] = color;
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[inline(always)]
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::ZERO;
+    }
+    // Mantissas are stored scaled by 256.
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    vec3!(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale
+    )
+}
+
+/// Reads one run-length encoded component of a scanline into `out`.
+fn read_rle_component(data: &[u8], pos: &mut usize, out: &mut [u8]) -> io::Result<()> {
+    let truncated = || invalid_data("truncated scanline");
+    let mut x = 0;
+    while x < out.len() {
+        let count = *data.get(*pos).ok_or_else(truncated)? as usize;
+        *pos += 1;
+        if count > 128 {
+            let count = count - 128;
+            let value = *data.get(*pos).ok_or_else(truncated)?;
+            *pos += 1;
+            if x + count > out.len() {
+                return Err(invalid_data("run past the end of a scanline"));
+            }
+            out[x..x + count].fill(value);
+            x += count;
+        } else {
+            if count == 0 || x + count > out.len() {
+                return Err(invalid_data("invalid run in scanline"));
+            }
+            let bytes = data.get(*pos..*pos + count).ok_or_else(truncated)?;
+            out[x..x + count].copy_from_slice(bytes);
+            *pos += count;
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Radiance RGBE file in the standard `-Y height +X width`
+/// orientation, flat or with per-component run-length encoding.
+pub fn load_hdr(path: &Path) -> io::Result<ImageBuffer> {
+    let data = fs::read(path)?;
+    if !data.starts_with(b"#?") {
+        return Err(invalid_data("not a Radiance file"));
+    }
+
+    // The header ends with an empty line, followed by the resolution line.
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> io::Result<String> {
+        let end = data[*pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| invalid_data("truncated header"))?;
+        let line = String::from_utf8_lossy(&data[*pos..*pos + end]).into_owned();
+        *pos += end + 1;
+        Ok(line)
+    };
+    loop {
+        let line = next_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported format '{}'", format)));
+            }
+        }
+    }
+    let resolution = next_line(&mut pos)?;
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => match (w.parse::<u32>(), h.parse::<u32>()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(invalid_data("invalid resolution")),
+        },
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported orientation '{}'",
+                resolution
+            )));
+        }
+    };
+
+    let mut image = ImageBuffer::new(width, height);
+    let mut components = vec![0u8; 4 * width as usize];
+    for y in 0..height {
+        let header = data.get(pos..pos + 4).unwrap_or(&[]);
+        let is_rle = (8..0x8000).contains(&width)
+            && header.len() == 4
+            && header[0] == 2
+            && header[1] == 2
+            && ((header[2] as u32) << 8 | header[3] as u32) == width;
+
+        if is_rle {
+            pos += 4;
+            for component in components.chunks_exact_mut(width as usize) {
+                read_rle_component(&data, &mut pos, component)?;
+            }
+            let (r, rest) = components.split_at(width as usize);
+            let (g, rest) = rest.split_at(width as usize);
+            let (b, e) = rest.split_at(width as usize);
+            for x in 0..width as usize {
+                image.set(x as u32, y, rgbe_to_color([r[x], g[x], b[x], e[x]]));
+            }
+        } else {
+            let bytes = data
+                .get(pos..pos + 4 * width as usize)
+                .ok_or_else(|| invalid_data("truncated scanline"))?;
+            for (x, rgbe) in bytes.chunks_exact(4).enumerate() {
+                image.set(
+                    x as u32,
+                    y,
+                    rgbe_to_color([rgbe[0], rgbe[1], rgbe[2], rgbe[3]]),
+                );
+            }
+            pos += 4 * width as usize;
+        }
+    }
+
+    Ok(image)
+}
+
+pub fn load_exr(path: &Path) -> io::Result<ImageBuffer> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| ImageBuffer::new(resolution.width() as u32, resolution.height() as u32),
+        |image: &mut ImageBuffer, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            image.set(position.x() as u32, position.y() as u32, vec3!(r, g, b));
+        },
+    )
+    .map_err(|e| match e {
+        exr::error::Error::Io(e) => e,
+        e => invalid_data(e.to_string()),
+    })?;
+    Ok(image.layer_data.channel_data.pixels)
+}
+
+/// How the values of 8 and 16-bit images are encoded. Color is sRGB, while
+/// data like normal and height maps is stored as is.
+#[derive(Clone, Copy)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub const NAMES: &'static str = "srgb, linear";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "srgb" => Some(Self::Srgb),
+            "linear" => Some(Self::Linear),
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    fn decode(self, x: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb => srgb_to_linear(x),
+            ColorSpace::Linear => x,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Which part of an image a texture reads.
+#[derive(Clone, Copy)]
+pub enum ImageChannel {
+    Color,
+    /// The alpha channel, as a grey image.
+    Alpha,
+}
+
+impl ImageChannel {
+    pub const NAMES: &'static str = "color, alpha";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "color" => Some(Self::Color),
+            "alpha" => Some(Self::Alpha),
+            _ => None,
+        }
+    }
+}
+
+/// Loads an 8 or 16-bit PNG, converting from `color_space` to linear. Alpha
+/// is ignored.
+pub fn load_png(path: &Path, color_space: ColorSpace) -> io::Result<ImageBuffer> {
+    read_png(path, |samples, channels| {
+        if channels < 3 {
+            let grey = color_space.decode(samples[0]);
+            vec3!(grey)
+        } else {
+            vec3!(
+                color_space.decode(samples[0]),
+                color_space.decode(samples[1]),
+                color_space.decode(samples[2])
+            )
+        }
+    })
+}
+
+/// Loads the alpha channel of a PNG, which is always linear. Images without
+/// one give their grey level instead.
+fn load_png_alpha(path: &Path) -> io::Result<ImageBuffer> {
+    read_png(path, |samples, channels| {
+        let alpha = match channels {
+            2 | 4 => samples[channels - 1],
+            1 => samples[0],
+            _ => (samples[0] + samples[1] + samples[2]) / 3.0,
+        };
+        vec3!(alpha)
+    })
+}
+
+/// Decodes a PNG, turning each pixel's samples, normalized to [0, 1], into
+/// a color with `pixel`.
+fn read_png<F>(path: &Path, pixel: F) -> io::Result<ImageBuffer>
+where
+    F: Fn(&[f32], usize) -> Color,
+{
+    let mut decoder = png::Decoder::new(fs::File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| invalid_data(e.to_string()))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut data)
+        .map_err(|e| invalid_data(e.to_string()))?;
+
+    let channels = info.color_type.samples();
+    let (max, bytes) = match info.bit_depth {
+        png::BitDepth::Sixteen => (65535.0, 2),
+        _ => (255.0, 1),
+    };
+    let sample = |i: usize| {
+        let value = if bytes == 2 {
+            u16::from_be_bytes([data[2 * i], data[2 * i + 1]]) as f32
+        } else {
+            data[i] as f32
+        };
+        value / max
+    };
+
+    let mut image = ImageBuffer::new(info.width, info.height);
+    let mut samples = [0.0; 4];
+    for y in 0..info.height {
+        for x in 0..info.width {
+            let i = (y as usize * info.width as usize + x as usize) * channels;
+            for (c, s) in samples.iter_mut().enumerate().take(channels) {
+                *s = sample(i + c);
+            }
+            image.set(x, y, pixel(&samples, channels));
+        }
+    }
+    Ok(image)
+}
+
+/// Loads a BMP, converting from `color_space` to linear.
+pub fn load_bmp(path: &Path, color_space: ColorSpace) -> io::Result<ImageBuffer> {
+    let bmp = bmp::open(path).map_err(|e| invalid_data(e.to_string()))?;
+    let mut image = ImageBuffer::new(bmp.get_width(), bmp.get_height());
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let p = bmp.get_pixel(x, y);
+            image.set(
+                x,
+                y,
+                vec3!(
+                    color_space.decode(p.r as f32 / 255.0),
+                    color_space.decode(p.g as f32 / 255.0),
+                    color_space.decode(p.b as f32 / 255.0)
+                ),
+            );
+        }
+    }
+    Ok(image)
+}
+
+/// Loads any supported image as linear RGB, picking the format from the
+/// extension. `color_space` only applies to PNG and BMP; HDR and EXR images
+/// are always linear.
+pub fn load_image(path: &Path, color_space: ColorSpace) -> io::Result<ImageBuffer> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => load_png(path, color_space),
+        Some("bmp") => load_bmp(path, color_space),
+        _ => load_hdr_image(path),
+    }
+}
+
+/// Loads the alpha channel of an image as a grey image, for masks. Only PNG
+/// files are read with alpha; other images and PNGs without alpha are read
+/// as linear data, so black and white masks work as well.
+pub fn load_image_alpha(path: &Path) -> io::Result<ImageBuffer> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => load_png_alpha(path),
+        _ => load_image(path, ColorSpace::Linear),
+    }
+}
+
+/// Loads a high dynamic range image, picking the format from the extension.
+pub fn load_hdr_image(path: &Path) -> io::Result<ImageBuffer> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("hdr") => load_hdr(path),
+        Some("exr") => load_exr(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unsupported image format, expected .hdr or .exr",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn rgbe_decoding() {
+        // Mantissas decode to the middle of their interval.
+        assert!(rgbe_to_color([128, 64, 0, 129]) == vec3!(128.5, 64.5, 0.5) / 128.0);
+        assert!(rgbe_to_color([255, 255, 255, 0]) == Color::ZERO);
+    }
+
+    #[test]
+    fn rle_component_decoding() {
+        let data = [2, 1, 2, 128 + 4, 7, 1, 3, 99];
+        let mut out = [0u8; 7];
+        let mut pos = 0;
+        read_rle_component(&data, &mut pos, &mut out).unwrap();
+        assert_eq!(out, [1, 2, 7, 7, 7, 7, 3]);
+        assert_eq!(pos, 7);
+
+        let mut out = [0u8; 3];
+        for data in [&[128 + 4, 7][..], &[4, 1, 2, 3, 4], &[0], &[2, 1]] {
+            assert!(read_rle_component(data, &mut 0, &mut out).is_err());
+        }
+    }
+
+    #[test]
+    fn loads_flat_and_rle_scanlines() {
+        let dir = TempDir::new();
+        let mut flat = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        flat.extend_from_slice(&[128, 128, 128, 129, 128, 0, 0, 130]);
+        let image = load_hdr(&dir.write("flat.hdr", &flat)).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!(image.get(0, 0) == vec3!(128.5, 128.5, 128.5) / 128.0);
+        assert!(image.get(1, 0) == vec3!(128.5, 0.5, 0.5) / 64.0);
+
+        // Red ramps up, green and blue are constant.
+        let mut rle = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        rle.extend_from_slice(&[2, 2, 0, 8]);
+        rle.extend_from_slice(&[8, 0, 32, 64, 96, 128, 160, 192, 224]);
+        rle.extend_from_slice(&[128 + 8, 128]);
+        rle.extend_from_slice(&[128 + 8, 0]);
+        rle.extend_from_slice(&[128 + 8, 129]);
+        let image = load_hdr(&dir.write("rle.hdr", &rle)).unwrap();
+        for x in 0..8 {
+            assert!(image.get(x, 0) == vec3!(32.0 * x as f32 + 0.5, 128.5, 0.5) / 128.0);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let dir = TempDir::new();
+        for (name, contents) in [
+            ("magic.hdr", &b"P6\n1 1\n"[..]),
+            (
+                "format.hdr",
+                b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n",
+            ),
+            ("orientation.hdr", b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0"),
+            ("truncated.hdr", b"#?RADIANCE\n\n-Y 2 +X 1\n\0\0\0\0"),
+        ] {
+            assert!(load_hdr(&dir.write(name, contents)).is_err(), "{}", name);
+        }
+    }
+}
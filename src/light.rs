@@ -0,0 +1,564 @@
+use crate::environment::*;
+use crate::random::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Emitter that can be sampled directly. Area lights are built from the
+/// emissive shapes of the scene; meshes contribute one triangle per face
+/// and boxes one quad per side. Emissive planes are infinite and are only
+/// found by BSDF sampling.
+/// Point, spot and directional lights are declared on their own and have no
+/// geometry, so only light sampling ever finds them. The environment is
+/// found by rays that miss everything.
+#[derive(Clone)]
+pub enum Light {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        emission: Color,
+    },
+    Triangle {
+        v0: Vec3,
+        v1: Vec3,
+        v2: Vec3,
+        emission: Color,
+    },
+    /// Parallelogram spanned by `u` and `v` from the corner `q`.
+    Quad {
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        emission: Color,
+    },
+    Disk {
+        center: Vec3,
+        /// Normalized.
+        normal: Vec3,
+        radius: f32,
+        emission: Color,
+    },
+    /// Radiant intensity `intensity`, falling off with the squared distance.
+    Point {
+        position: Vec3,
+        intensity: Color,
+    },
+    /// Point light restricted to a cone around `direction` (normalized),
+    /// fading out between the cosines of the falloff start and cone angles.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        intensity: Color,
+        cos_falloff_start: f32,
+        cos_cone: f32,
+    },
+    /// Light arriving from `direction` (normalized, pointing towards the
+    /// light) with irradiance `irradiance`, spread over a disk of the given
+    /// angular radius, or a single direction if it is zero. A disk is seen
+    /// by rays that escape the scene, like the sun.
+    Directional {
+        direction: Vec3,
+        irradiance: Color,
+        cos_angular_radius: f32,
+    },
+    Environment(Arc<Environment>),
+}
+
+/// Direction towards a point on a light, as seen from the shading point.
+pub struct LightSample {
+    /// Unit vector towards the light.
+    pub wi: Vec3,
+    pub distance: f32,
+    pub radiance: Color,
+    /// Solid angle density of `wi`, or the discrete probability for lights
+    /// that can't be hit by rays.
+    pub pdf: f32,
+    /// Set for lights without geometry, which get the full weight since
+    /// BSDF sampling can't find them.
+    pub delta: bool,
+}
+
+/// `1 - cos(theta_max)` of the cone subtended by a sphere, accurate for
+/// small and distant spheres where the cosine rounds to one.
+#[inline(always)]
+fn cone_one_minus_cos(sin2_theta_max: f32) -> f32 {
+    if sin2_theta_max < 0.00068523 {
+        0.5 * sin2_theta_max
+    } else {
+        1.0 - f32::sqrt(1.0 - sin2_theta_max)
+    }
+}
+
+#[inline(always)]
+fn smoothstep(a: f32, b: f32, x: f32) -> f32 {
+    if a == b {
+        return if x < a { 0.0 } else { 1.0 };
+    }
+    let t = ((x - a) / (b - a)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[inline(always)]
+fn area_to_solid_angle(pdf_area: f32, distance_squared: f32, cos_light: f32) -> f32 {
+    if cos_light <= 0.0 {
+        return 0.0;
+    }
+    pdf_area * distance_squared / cos_light
+}
+
+impl Light {
+    /// Picks a point on the light visible from `p`. Returns `None` when the
+    /// sample carries no energy.
+    #[inline(always)]
+    pub fn sample(&self, p: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        match *self {
+            Light::Sphere {
+                center,
+                radius,
+                emission,
+            } => {
+                let to_center = center - p;
+                let dist_squared = to_center.lensq();
+                let radius_squared = radius * radius;
+
+                if dist_squared <= radius_squared {
+                    // Inside the sphere every point is visible: sample the
+                    // surface uniformly.
+                    let normal = Vec3::random_unit_vector(rng);
+                    let q = center + radius * normal;
+                    let d = q - p;
+                    let distance = d.len();
+                    if distance <= 0.0 {
+                        return None;
+                    }
+                    let wi = d / distance;
+                    let pdf = area_to_solid_angle(
+                        1.0 / (4.0 * PI * radius_squared),
+                        distance * distance,
+                        wi.dot(normal).abs(),
+                    );
+                    return Some(LightSample {
+                        wi,
+                        distance,
+                        radiance: emission,
+                        pdf,
+                        delta: false,
+                    });
+                }
+
+                // Sample the cone of directions subtended by the sphere.
+                let dist = dist_squared.sqrt();
+                let w = to_center / dist;
+                let one_minus_cos_max = cone_one_minus_cos(radius_squared / dist_squared);
+                let cos_theta = 1.0 - rng.randomf32() * one_minus_cos_max;
+                let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+                let phi = 2.0 * PI * rng.randomf32();
+                let (u, v) = w.orthonormal_basis();
+                let wi = (u * phi.cos() + v * phi.sin()) * sin_theta + w * cos_theta;
+
+                // Distance to the near side of the sphere along `wi`.
+                let b = wi.dot(to_center);
+                let distance = b - f32::sqrt(f32::max(0.0, b * b - dist_squared + radius_squared));
+
+                Some(LightSample {
+                    wi,
+                    distance,
+                    radiance: emission,
+                    pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+                    delta: false,
+                })
+            }
+            Light::Triangle {
+                v0,
+                v1,
+                v2,
+                emission,
+            } => {
+                let su = rng.randomf32().sqrt();
+                let b1 = rng.randomf32() * su;
+                let q = (1.0 - su) * v0 + (su - b1) * v1 + b1 * v2;
+
+                let cross = (v1 - v0).cross(v2 - v0);
+                let area = 0.5 * cross.len();
+                let d = q - p;
+                let distance = d.len();
+                if area <= 0.0 || distance <= 0.0 {
+                    return None;
+                }
+                let wi = d / distance;
+                let cos_light = wi.dot(cross.normalized()).abs();
+                let pdf = area_to_solid_angle(1.0 / area, distance * distance, cos_light);
+                if pdf <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample {
+                    wi,
+                    distance,
+                    radiance: emission,
+                    pdf,
+                    delta: false,
+                })
+            }
+            Light::Quad { q, u, v, emission } => {
+                let cross = u.cross(v);
+                let area = cross.len();
+                let point = q + rng.randomf32() * u + rng.randomf32() * v;
+                let d = point - p;
+                let distance = d.len();
+                if area <= 0.0 || distance <= 0.0 {
+                    return None;
+                }
+                let wi = d / distance;
+                let cos_light = wi.dot(cross / area).abs();
+                let pdf = area_to_solid_angle(1.0 / area, distance * distance, cos_light);
+                if pdf <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample {
+                    wi,
+                    distance,
+                    radiance: emission,
+                    pdf,
+                    delta: false,
+                })
+            }
+            Light::Disk {
+                center,
+                normal,
+                radius,
+                emission,
+            } => {
+                let area = PI * radius * radius;
+                let r = radius * rng.randomf32().sqrt();
+                let phi = 2.0 * PI * rng.randomf32();
+                let (s, t) = normal.orthonormal_basis();
+                let point = center + r * (phi.cos() * s + phi.sin() * t);
+                let d = point - p;
+                let distance = d.len();
+                if area <= 0.0 || distance <= 0.0 {
+                    return None;
+                }
+                let wi = d / distance;
+                let cos_light = wi.dot(normal).abs();
+                let pdf = area_to_solid_angle(1.0 / area, distance * distance, cos_light);
+                if pdf <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample {
+                    wi,
+                    distance,
+                    radiance: emission,
+                    pdf,
+                    delta: false,
+                })
+            }
+            Light::Point {
+                position,
+                intensity,
+            } => {
+                let d = position - p;
+                let distance_squared = d.lensq();
+                if distance_squared <= 0.0 {
+                    return None;
+                }
+                let distance = distance_squared.sqrt();
+                Some(LightSample {
+                    wi: d / distance,
+                    distance,
+                    radiance: intensity / distance_squared,
+                    pdf: 1.0,
+                    delta: true,
+                })
+            }
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                cos_falloff_start,
+                cos_cone,
+            } => {
+                let d = position - p;
+                let distance_squared = d.lensq();
+                if distance_squared <= 0.0 {
+                    return None;
+                }
+                let distance = distance_squared.sqrt();
+                let wi = d / distance;
+                let falloff = smoothstep(cos_cone, cos_falloff_start, (-wi).dot(direction));
+                if falloff <= 0.0 {
+                    return None;
+                }
+                Some(LightSample {
+                    wi,
+                    distance,
+                    radiance: intensity * (falloff / distance_squared),
+                    pdf: 1.0,
+                    delta: true,
+                })
+            }
+            Light::Directional {
+                direction,
+                irradiance,
+                cos_angular_radius,
+            } => {
+                let one_minus_cos_max = 1.0 - cos_angular_radius;
+                if one_minus_cos_max <= 0.0 {
+                    return Some(LightSample {
+                        wi: direction,
+                        distance: f32::MAX,
+                        radiance: irradiance,
+                        pdf: 1.0,
+                        delta: true,
+                    });
+                }
+
+                // Uniform direction within the disk; dividing by the density
+                // gives back the irradiance.
+                let cos_theta = 1.0 - rng.randomf32() * one_minus_cos_max;
+                let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+                let phi = 2.0 * PI * rng.randomf32();
+                let (u, v) = direction.orthonormal_basis();
+                let solid_angle = 2.0 * PI * one_minus_cos_max;
+                Some(LightSample {
+                    wi: (u * phi.cos() + v * phi.sin()) * sin_theta + direction * cos_theta,
+                    distance: f32::MAX,
+                    radiance: irradiance / solid_angle,
+                    pdf: 1.0 / solid_angle,
+                    delta: false,
+                })
+            }
+            Light::Environment(ref environment) => {
+                let (wi, radiance, pdf) = environment.sample(rng)?;
+                Some(LightSample {
+                    wi,
+                    distance: f32::MAX,
+                    radiance,
+                    pdf,
+                    delta: false,
+                })
+            }
+        }
+    }
+
+    /// Radiance of a directional light's disk seen along `direction`
+    /// (normalized) and the density with which `sample` picks it, or `None`
+    /// if the direction misses the disk.
+    #[inline(always)]
+    pub fn sun_disk_radiance(&self, direction: Vec3) -> Option<(Color, f32)> {
+        match *self {
+            Light::Directional {
+                direction: towards_light,
+                irradiance,
+                cos_angular_radius,
+            } if cos_angular_radius < 1.0 && direction.dot(towards_light) >= cos_angular_radius => {
+                let solid_angle = 2.0 * PI * (1.0 - cos_angular_radius);
+                Some((irradiance / solid_angle, 1.0 / solid_angle))
+            }
+            _ => None,
+        }
+    }
+
+    /// Solid angle density with which `sample` would have picked the point
+    /// `q` (with surface normal `n`) as seen from `p`.
+    #[inline(always)]
+    pub fn pdf(&self, p: Vec3, q: Vec3, n: Vec3) -> f32 {
+        let d = q - p;
+        let distance_squared = d.lensq();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+        let cos_light = d.dot(n).abs() / distance_squared.sqrt();
+
+        match *self {
+            Light::Sphere { center, radius, .. } => {
+                let radius_squared = radius * radius;
+                let dist_squared = (center - p).lensq();
+                if dist_squared <= radius_squared {
+                    return area_to_solid_angle(
+                        1.0 / (4.0 * PI * radius_squared),
+                        distance_squared,
+                        cos_light,
+                    );
+                }
+                1.0 / (2.0 * PI * cone_one_minus_cos(radius_squared / dist_squared))
+            }
+            Light::Triangle { v0, v1, v2, .. } => {
+                let area = 0.5 * (v1 - v0).cross(v2 - v0).len();
+                if area <= 0.0 {
+                    return 0.0;
+                }
+                area_to_solid_angle(1.0 / area, distance_squared, cos_light)
+            }
+            Light::Quad { u, v, .. } => {
+                let area = u.cross(v).len();
+                if area <= 0.0 {
+                    return 0.0;
+                }
+                area_to_solid_angle(1.0 / area, distance_squared, cos_light)
+            }
+            Light::Disk { radius, .. } => {
+                let area = PI * radius * radius;
+                if area <= 0.0 {
+                    return 0.0;
+                }
+                area_to_solid_angle(1.0 / area, distance_squared, cos_light)
+            }
+            // Not found by hitting geometry, see `HittableList::environment_pdf`
+            // and `sun_disk_radiance`.
+            Light::Point { .. }
+            | Light::Spot { .. }
+            | Light::Directional { .. }
+            | Light::Environment(_) => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Relative difference, for densities that span orders of magnitude.
+    fn assert_close(a: f32, b: f32) {
+        assert!(
+            (a - b).abs() <= 1e-3 * a.abs().max(b.abs()),
+            "{} != {}",
+            a,
+            b
+        );
+    }
+
+    /// Samples `light` from `p` and checks that `pdf` gives back the
+    /// density of every sample; `normal` is the light's surface normal at
+    /// a sampled point.
+    fn check_pdf(light: &Light, p: Vec3, normal: impl Fn(Vec3) -> Vec3) {
+        let mut rng = Rng::new(3);
+        let mut count = 0;
+        for _ in 0..1000 {
+            let Some(sample) = light.sample(p, &mut rng) else {
+                continue;
+            };
+            assert!(!sample.delta);
+            let q = p + sample.wi * sample.distance;
+            assert_close(light.pdf(p, q, normal(q)), sample.pdf);
+            count += 1;
+        }
+        assert!(count > 900);
+    }
+
+    #[test]
+    fn area_light_pdf_matches_sample() {
+        let center = vec3!(1, 2, -3);
+        let sphere = Light::Sphere {
+            center,
+            radius: 0.5,
+            emission: color!(1.0),
+        };
+        let sphere_normal = |q: Vec3| (q - center).normalized();
+        check_pdf(&sphere, vec3!(0, 0, 0), sphere_normal);
+        check_pdf(&sphere, vec3!(1, 2, -3.2), sphere_normal);
+        check_pdf(
+            &sphere,
+            vec3!(1, 2, -3) + 400.0 * vec3!(1, 1, 1),
+            sphere_normal,
+        );
+
+        let (v0, v1, v2) = (vec3!(-1, 2, 0), vec3!(1, 2, 0.5), vec3!(0, 3, 1));
+        let triangle = Light::Triangle {
+            v0,
+            v1,
+            v2,
+            emission: color!(1.0),
+        };
+        let triangle_normal = |_| (v1 - v0).cross(v2 - v0).normalized();
+        check_pdf(&triangle, vec3!(0, 0, 0), triangle_normal);
+        check_pdf(&triangle, vec3!(0.3, 4, -2), triangle_normal);
+    }
+
+    #[test]
+    fn quad_and_disk_light_pdf_matches_sample() {
+        let (u, v) = (vec3!(2, 0, 0), vec3!(0, 0.5, 1));
+        let quad = Light::Quad {
+            q: vec3!(-1, 1, 0),
+            u,
+            v,
+            emission: color!(1.0),
+        };
+        let quad_normal = |_| u.cross(v).normalized();
+        check_pdf(&quad, vec3!(0, 0, 0), quad_normal);
+        check_pdf(&quad, vec3!(0.5, 3, -1), quad_normal);
+
+        let normal = vec3!(0, -1, 1).normalized();
+        let disk = Light::Disk {
+            center: vec3!(0, 2, 2),
+            normal,
+            radius: 0.7,
+            emission: color!(1.0),
+        };
+        check_pdf(&disk, vec3!(0, 0, 3), |_| normal);
+        check_pdf(&disk, vec3!(1, 4, 1), |_| normal);
+    }
+
+    #[test]
+    fn sun_disk_matches_directional_sample() {
+        let direction = vec3!(0.3, 1, -0.2).normalized();
+        let sun = Light::Directional {
+            direction,
+            irradiance: color!(2.0),
+            cos_angular_radius: 0.05f32.cos(),
+        };
+        let mut rng = Rng::new(5);
+        for _ in 0..1000 {
+            let sample = sun.sample(vec3!(0, 0, 0), &mut rng).unwrap();
+            assert!(!sample.delta);
+            let (radiance, pdf) = sun.sun_disk_radiance(sample.wi).unwrap();
+            assert_close(pdf, sample.pdf);
+            assert!(radiance == sample.radiance);
+        }
+        assert!(sun.sun_disk_radiance(-direction).is_none());
+    }
+
+    #[test]
+    fn delta_lights_have_no_density() {
+        let p = vec3!(0, 0, 0);
+        let lights = [
+            Light::Point {
+                position: vec3!(1, 2, 3),
+                intensity: color!(1.0),
+            },
+            Light::Spot {
+                position: vec3!(0, 2, 0),
+                direction: vec3!(0, -1, 0),
+                intensity: color!(1.0),
+                cos_falloff_start: 0.9,
+                cos_cone: 0.8,
+            },
+            Light::Directional {
+                direction: vec3!(0, 1, 0),
+                irradiance: color!(1.0),
+                cos_angular_radius: 1.0,
+            },
+        ];
+        let mut rng = Rng::new(7);
+        for light in &lights {
+            let sample = light.sample(p, &mut rng).unwrap();
+            assert!(sample.delta);
+            assert!(sample.pdf == 1.0);
+            assert!(light.pdf(p, p + sample.wi, -sample.wi) == 0.0);
+        }
+    }
+
+    #[test]
+    fn spot_light_is_dark_outside_its_cone() {
+        let spot = Light::Spot {
+            position: vec3!(0, 2, 0),
+            direction: vec3!(0, -1, 0),
+            intensity: color!(1.0),
+            cos_falloff_start: 0.9,
+            cos_cone: 0.8,
+        };
+        assert!(spot.sample(vec3!(5, 0, 0), &mut Rng::new(1)).is_none());
+    }
+}
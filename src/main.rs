@@ -1,359 +1,558 @@
 #[macro_use]
 extern crate bmp;
-extern crate libc;
 
+mod aabb;
+mod alpha;
+mod bvh;
 mod camera;
+mod cli;
+mod cuboid;
+mod disk;
+mod distribution;
+mod environment;
+mod film;
 mod hittable;
 mod hittable_list;
+mod image;
+mod light;
 mod material;
+mod microfacet;
+mod noise;
+mod normal_map;
+mod obj;
+mod output;
+mod physical_sky;
+mod plane;
+mod principled;
+mod quad;
 mod random;
 mod ray;
+mod scene;
+mod shape;
+mod sky;
 mod sphere;
+#[cfg(test)]
+mod test_util;
+mod texture;
+mod thread_pool;
+mod tonemap;
+mod triangle;
+mod triangle_mesh;
 mod vec3;
 
-use bmp::{Image, Pixel};
+use bvh::*;
 use camera::*;
+use cli::*;
+use film::*;
 use hittable::*;
 use hittable_list::*;
+use light::*;
 use material::*;
+use output::*;
 use random::*;
 use ray::*;
+use scene::*;
+use sky::*;
 use sphere::*;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
+use thread_pool::*;
+use tonemap::*;
 use vec3::*;
 
-const ASPECT_RATIO: f32 = 3.0 / 2.0;
-const WIDTH: u32 = 1200u32;
-const HEIGHT: u32 = (WIDTH as f32 / ASPECT_RATIO) as u32;
-
+#[derive(Clone, Copy)]
 struct RaytracerConfig {
+    film: Film,
     max_threads: u32,
     samples_per_pixel: u32,
     max_bounces: u32,
     tile_size: u32,
+    seed: u32,
+    tone_mapping: ToneMapping,
+}
+
+impl Default for RaytracerConfig {
+    fn default() -> Self {
+        Self {
+            film: Film::new(1200, 800),
+            max_threads: thread::available_parallelism().map_or(1, |n| n.get() as u32),
+            tile_size: 64,
+            samples_per_pixel: 512,
+            max_bounces: 8,
+            seed: 8589392,
+            tone_mapping: ToneMapping::default(),
+        }
+    }
 }
 
 struct Profile {
     total_time: Duration,
-    total_bounces: AtomicU64,
+    total_bounces: u64,
+    bvh_stats: BvhStats,
 }
 
-unsafe impl Send for Profile {}
-unsafe impl Sync for Profile {}
-
-#[derive(Clone, Copy)]
 struct RenderBuffer {
-    buffer: *mut Vec3,
+    pixels: Vec<Vec3>,
     w: u32,
     h: u32,
 }
 
-unsafe impl Send for RenderBuffer {}
-unsafe impl Sync for RenderBuffer {}
-
 impl RenderBuffer {
     fn new(w: u32, h: u32) -> Self {
-        unsafe {
-            let buffer =
-                libc::malloc(std::mem::size_of::<Vec3>() * w as libc::size_t * h as libc::size_t)
-                    as *mut Vec3;
-            if buffer.is_null() {
-                println!("failed to allocate memory!!!")
-            }
-            Self { buffer, w, h }
+        Self {
+            pixels: vec![Vec3::ZERO; w as usize * h as usize],
+            w,
+            h,
         }
     }
 
     #[inline(always)]
     fn get_pixel_color(&self, x: u32, y: u32) -> Vec3 {
-        unsafe {
-            let buffer = self
-                .buffer
-                .offset(y as isize * self.w as isize)
-                .offset(x as isize);
-            *buffer
-        }
+        self.pixels[y as usize * self.w as usize + x as usize]
     }
-}
 
-#[inline(always)]
-fn write_color(
-    render_buffer: &mut RenderBuffer,
-    x: u32,
-    y: u32,
-    color: Color,
-    samples_per_pixel: u32,
-) {
-    let px = color * (1.0 / samples_per_pixel as f32);
-    let r = px.x.sqrt();
-    let g = px.y.sqrt();
-    let b = px.z.sqrt();
-    unsafe {
-        let px = render_buffer
-            .buffer
-            .offset(y as isize * render_buffer.w as isize)
-            .offset(x as isize) as *mut Vec3;
-        *px = vec3!(r, g, b);
+    /// Copies a finished tile into place. `origin` is the film pixel stored
+    /// at (0, 0) of the buffer, i.e. the corner of the crop window.
+    fn write_tile(&mut self, tile: &Tile, origin_x: u32, origin_y: u32) {
+        let w = tile.bounds.width() as usize;
+        for (row, y) in (tile.bounds.y0..tile.bounds.y1).enumerate() {
+            let start =
+                (y - origin_y) as usize * self.w as usize + (tile.bounds.x0 - origin_x) as usize;
+            self.pixels[start..start + w].copy_from_slice(&tile.pixels[row * w..(row + 1) * w]);
+        }
     }
 }
 
-#[inline(always)]
-fn ray_color_expand(r: Ray, world: &HittableList, depth: u32) -> Color {
-    let unit_direction = r.direction.normalized();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    let mut result = Vec3::lerp(color!(1.0), t, color!(0.5, 0.7, 1.0));
-    let mut scattered = r;
-    let mut attenuation = Color::ZERO;
-
-    let mut rec = HitRecord::new();
+/// Pixels rendered by one job, in film coordinates.
+struct Tile {
+    bounds: PixelBounds,
+    pixels: Vec<Color>,
+}
 
-    for d in 0..depth {
-        if d == depth {
-            return Color::ZERO;
+impl Tile {
+    fn new(bounds: PixelBounds) -> Self {
+        Self {
+            bounds,
+            pixels: Vec::with_capacity(bounds.width() as usize * bounds.height() as usize),
         }
+    }
+}
 
-        if world.hit(scattered, 0.001, f32::MAX, &mut rec) {
-            if rec
-                .material
-                .scatter(scattered, rec, &mut attenuation, &mut scattered)
-            {
-                result = Vec3::hadamard(attenuation, result);
-                continue;
-            }
+/// Stores the pixel's average radiance. Encoding for display happens when
+/// the image is written.
+#[inline(always)]
+fn write_color(tile: &mut Tile, color: Color, samples_per_pixel: u32) {
+    tile.pixels.push(color * (1.0 / samples_per_pixel as f32));
+}
 
-            return Color::ZERO;
-        }
+/// Power heuristic (beta = 2) weight of a sample drawn with density `pdf`
+/// when the other strategy would have drawn it with `other_pdf`.
+#[inline(always)]
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        return 0.0;
     }
-
-    result
+    a / (a + b)
 }
 
+/// Path tracer with next event estimation: at every diffuse hit a light is
+/// sampled explicitly and the result is combined with the BSDF sampled
+/// path through multiple importance sampling.
 #[inline(always)]
-fn ray_color(r: Ray, world: &HittableList, depth: u32) -> Color {
+fn ray_color(
+    r: Ray,
+    world: &HittableList,
+    background: &Background,
+    depth: u32,
+    rng: &mut Rng,
+) -> Color {
+    let mut result = Color::ZERO;
+    let mut throughput = color!(1.0);
+    let mut ray = r;
     let mut rec = HitRecord::new();
 
-    if depth <= 0 {
-        return Color::ZERO;
-    }
+    // Emission found by the BSDF sampled ray is weighted against light
+    // sampling from the previous vertex, unless that vertex was specular.
+    let mut prev_p = ray.origin;
+    let mut prev_pdf = 0.0;
+    let mut prev_specular = true;
+
+    for _ in 0..depth {
+        if !world.hit(ray, 0.001, f32::MAX, &mut rec) {
+            let weight = if prev_specular {
+                1.0
+            } else {
+                power_heuristic(prev_pdf, world.environment_pdf(ray.direction))
+            };
+            result += Vec3::hadamard(throughput, background.color(ray)) * weight;
+
+            let (sun_radiance, sun_pdf) = world.sun_disk_radiance(ray.direction);
+            if !sun_radiance.near_zero() {
+                let weight = if prev_specular {
+                    1.0
+                } else {
+                    power_heuristic(prev_pdf, sun_pdf)
+                };
+                result += Vec3::hadamard(throughput, sun_radiance) * weight;
+            }
+            break;
+        }
+
+        let material = world.materials.get(rec.material);
+        material.shade(&mut rec);
+        let wo = -ray.direction.normalized();
+        let emitted = material.emitted();
+        if !emitted.near_zero() {
+            let weight = if prev_specular {
+                1.0
+            } else {
+                power_heuristic(prev_pdf, world.light_pdf(prev_p, &rec))
+            };
+            result += Vec3::hadamard(throughput, emitted) * weight;
+        }
+
+        if !material.is_specular() {
+            if let Some(light) = world.sample_light(rec.p, rng) {
+                let f = material.eval(wo, light.wi, &rec);
+                if light.pdf > 0.0
+                    && !f.near_zero()
+                    && rec.is_consistent(wo, light.wi)
+                    && !world.occluded(
+                        Ray::new(rec.p, light.wi),
+                        0.001,
+                        light.distance * (1.0 - 1e-4),
+                    )
+                {
+                    let weight = if light.delta {
+                        1.0
+                    } else {
+                        power_heuristic(light.pdf, material.pdf(wo, light.wi, &rec))
+                    };
+                    result += Vec3::hadamard(throughput, Vec3::hadamard(f, light.radiance))
+                        * (weight / light.pdf);
+                }
+            }
+        }
 
-    if world.hit(r, 0.001, f32::MAX, &mut rec) {
-        let mut scattered = Ray::new(Vec3::ZERO, Vec3::ZERO);
-        let mut attenuation = Color::ZERO;
-        if rec
-            .material
-            .scatter(r, rec, &mut attenuation, &mut scattered)
-        {
-            let result = ray_color(scattered, world, depth - 1);
-            return Vec3::hadamard(attenuation, result);
+        let Some(sample) = material.sample(wo, &rec, rng) else {
+            break;
+        };
+        if !rec.is_consistent(wo, sample.wi) {
+            break;
         }
+        throughput = Vec3::hadamard(throughput, sample.weight);
 
-        return Color::ZERO;
+        prev_p = rec.p;
+        prev_specular = sample.specular;
+        prev_pdf = sample.pdf;
+        ray = Ray::new(rec.p, sample.wi);
     }
 
-    let unit_direction = r.direction.normalized();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    Vec3::lerp(color!(1.0), t, color!(0.5, 0.7, 1.0))
-    //(1.0 - t) * color!(1.0) + t * color!(0.5, 0.7, 1.0)
+    result
 }
 
 #[inline(always)]
 fn render_tile(
-    render_buffer: &mut RenderBuffer,
+    tile: &mut Tile,
     world: &HittableList,
+    background: &Background,
     camera: Camera,
-    tile_x: u32,
-    tile_y: u32,
-    tile_x2: u32,
-    tile_y2: u32,
-    samples_per_pixel: u32,
-    max_depth: u32,
+    config: RaytracerConfig,
 ) -> u64 {
+    let film = config.film;
+    let bounds = tile.bounds;
+    let samples_per_pixel = config.samples_per_pixel;
     let mut bounces = 0u64;
-    for y in tile_y..tile_y2 {
-        for x in tile_x..tile_x2 {
+    for y in bounds.y0..bounds.y1 {
+        for x in bounds.x0..bounds.x1 {
             let mut color = color!();
+            let mut rng = Rng::for_pixel(config.seed, x, y);
 
             for _ in 0..samples_per_pixel {
-                let u = (x as f32 + randomf32()) as f32 / (WIDTH - 1) as f32;
-                let v = (y as f32 + randomf32()) as f32 / (HEIGHT - 1) as f32;
-                let r = camera.get_ray(u, v);
-                color += ray_color(r, world, max_depth);
+                let u = (x as f32 + rng.randomf32()) / film.width as f32;
+                let v = 1.0 - (y as f32 + rng.randomf32()) / film.height as f32;
+                let r = camera.get_ray(u, v, &mut rng);
+                color += ray_color(r, world, background, config.max_bounces, &mut rng);
 
                 bounces += 1;
             }
 
-            write_color(render_buffer, x, HEIGHT - 1 - y, color, samples_per_pixel);
+            write_color(tile, color, samples_per_pixel);
         }
     }
 
     bounces
 }
 
-fn random_scene() -> HittableList {
+fn random_scene(rng: &mut Rng) -> HittableList {
     let mut world = HittableList::new();
 
-    let ground_material = Material::new(MaterialType::Lambertian, color!(0.5, 0.5, 0.5), 0.0);
+    let ground_material = world.add_material(Box::new(Lambertian::new(color!(0.5, 0.5, 0.5))));
     world.add_sphere(Sphere::new(vec3!(0, -1000, 0), 1000.0, ground_material));
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = randomf32();
+            let choose_mat = rng.randomf32();
             let center = vec3!(
-                a as f32 + 0.9 * randomf32(),
+                a as f32 + 0.9 * rng.randomf32(),
                 0.2,
-                b as f32 + 0.9 * randomf32()
+                b as f32 + 0.9 * rng.randomf32()
             );
 
             if (center - vec3!(4, 0.2, 0)).len() > 0.9 {
-                let sphere_material = if choose_mat < 0.8 {
-                    let albedo = Color::hadamard(Color::random(), Color::random());
-                    Material::new(MaterialType::Lambertian, albedo, 0.0)
+                let sphere_material: Box<dyn Material> = if choose_mat < 0.8 {
+                    let albedo = Color::hadamard(Color::random(rng), Color::random(rng));
+                    Box::new(Lambertian::new(albedo))
                 } else if choose_mat < 0.95 {
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = randomf32_range(0.0, 0.5);
-                    Material::new(MaterialType::Metal, albedo, fuzz)
+                    let albedo = Color::random_range(rng, 0.5, 1.0);
+                    let roughness = rng.randomf32_range(0.0, 0.5);
+                    Box::new(Conductor::from_reflectance(albedo, roughness))
                 } else {
-                    Material::new(MaterialType::Dieletric, Vec3::ZERO, 1.5)
+                    Box::new(Dieletric::new(1.5, 0.0))
                 };
 
+                let sphere_material = world.add_material(sphere_material);
                 world.add_sphere(Sphere::new(center, 0.2, sphere_material));
             }
         }
     }
 
-    let material1 = Material::new(MaterialType::Dieletric, Color::ZERO, 1.5);
+    let material1 = world.add_material(Box::new(Dieletric::new(1.5, 0.0)));
     world.add_sphere(Sphere::new(vec3!(0, 1, 0), 1.0, material1));
 
-    let material2 = Material::new(MaterialType::Lambertian, color!(0.4, 0.2, 0.1), 1.5);
+    let material2 = world.add_material(Box::new(Lambertian::new(color!(0.4, 0.2, 0.1))));
     world.add_sphere(Sphere::new(vec3!(-4, 1, 0), 1.0, material2));
 
-    let material3 = Material::new(MaterialType::Metal, color!(0.7, 0.6, 0.5), 0.0);
+    let material3 = world.add_material(Box::new(Conductor::from_reflectance(
+        color!(0.7, 0.6, 0.5),
+        0.0,
+    )));
     world.add_sphere(Sphere::new(vec3!(4, 1, 0), 1.0, material3));
 
     world
 }
 
-fn start_raytracer(config: RaytracerConfig) -> RenderBuffer {
-    let samples_per_pixel = config.samples_per_pixel;
-    let max_depth = config.max_bounces;
+fn builtin_random_scene(config: RaytracerConfig) -> Scene {
+    let camera = CameraSettings {
+        lookfrom: vec3!(13, 2, 3),
+        lookat: vec3!(0, 0, 0),
+        vup: vec3!(0, 1, 0),
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_dist: Some(10.0),
+    };
 
-    let mut profile = Arc::new(Profile {
-        total_time: Duration::ZERO,
-        total_bounces: AtomicU64::new(0),
-    });
+    let mut rng = Rng::new(config.seed);
+
+    Scene {
+        camera,
+        world: random_scene(&mut rng),
+        background: Background::default(),
+        lights: vec![],
+        config,
+    }
+}
+
+/// Fails if any tile job panicked.
+fn start_raytracer(scene: Scene, pool: &ThreadPool) -> Result<(RenderBuffer, Profile), String> {
+    let config = scene.config;
+    let crop = config.film.pixel_bounds();
+    let camera = scene.camera.build(&config.film);
 
     let total_bounces = Arc::new(AtomicU64::new(0));
 
-    let material_ground = Material::new(MaterialType::Lambertian, color!(0.8, 0.8, 0.0), 0.0);
-    let material_center = Material::new(MaterialType::Lambertian, color!(0.1, 0.2, 0.5), 0.0);
-    let material_left = Material::new(MaterialType::Dieletric, color!(0, 0, 1), 1.5);
-    let material_right = Material::new(MaterialType::Lambertian, color!(0.8, 0.6, 0.2), 0.0);
-
-    let mut world = Arc::new(random_scene());
-
-    let lookfrom = vec3!(13, 2, 3);
-    let lookat = vec3!(0, 0, 0);
-    let vup = vec3!(0, 1, 0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-    let camera = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
-    );
+    let mut world = scene.world;
+    let bvh_stats = world.build_bvh();
+    let mut lights = scene.lights;
+    match &scene.background {
+        Background::Environment(environment) => {
+            lights.push(Light::Environment(Arc::clone(environment)));
+        }
+        Background::PhysicalSky(sky) => lights.push(sky.sun_light()),
+        Background::Sky(_) => {}
+    }
+    world.build_lights(&lights);
+    let world = Arc::new(world);
+    let background = Arc::new(scene.background);
 
-    let mut image = Image::new(WIDTH, HEIGHT);
-    let mut render_buffer = RenderBuffer::new(WIDTH, HEIGHT);
+    let mut render_buffer = RenderBuffer::new(crop.width(), crop.height());
+    let (sender, receiver) = mpsc::channel::<Tile>();
+    let mut tile_count = 0;
 
     let before = Instant::now();
 
     let tile_w = config.tile_size;
     let tile_h = tile_w;
 
-    let tile_count_x = (WIDTH + tile_w - 1) / tile_w;
-    let tile_count_y = (HEIGHT + tile_h - 1) / tile_h;
-
-    let jobs_count = Arc::new(AtomicU32::new(0));
-    let max_jobs = config.max_threads;
+    let tile_count_x = crop.width().div_ceil(tile_w);
+    let tile_count_y = crop.height().div_ceil(tile_h);
 
     for y in 0..tile_count_y {
         for x in 0..tile_count_x {
-            let tile_x = x * tile_w;
-            let tile_y = y * tile_h;
-            let tile_x2 = u32::min(tile_x + tile_w, WIDTH);
-            let tile_y2 = u32::min(tile_y + tile_h, HEIGHT);
+            let tile_x = crop.x0 + x * tile_w;
+            let tile_y = crop.y0 + y * tile_h;
+            let bounds = PixelBounds {
+                x0: tile_x,
+                y0: tile_y,
+                x1: u32::min(tile_x + tile_w, crop.x1),
+                y1: u32::min(tile_y + tile_h, crop.y1),
+            };
 
-            while jobs_count.load(Ordering::Relaxed) >= max_jobs {}
-            jobs_count.fetch_add(1, Ordering::Relaxed);
-
-            //let mut tile = render_buffer.get_tile(tile_x, tile_y, tile_x2, tile_y2);
-            let jobs_count_clone = Arc::clone(&jobs_count);
             let world_clone = Arc::clone(&world);
-            let profile_clone = Arc::clone(&profile);
+            let background_clone = Arc::clone(&background);
             let total_bounces_clone = Arc::clone(&total_bounces);
-            thread::spawn(move || {
-                let bounces = render_tile(
-                    &mut render_buffer,
-                    &world_clone,
-                    camera,
-                    tile_x,
-                    tile_y,
-                    tile_x2,
-                    tile_y2,
-                    samples_per_pixel,
-                    max_depth,
-                );
+            let sender = sender.clone();
+            pool.execute(move || {
+                let mut tile = Tile::new(bounds);
+                let bounces =
+                    render_tile(&mut tile, &world_clone, &background_clone, camera, config);
                 total_bounces_clone.fetch_add(bounces, Ordering::Relaxed);
-                jobs_count_clone.fetch_sub(1, Ordering::Relaxed);
+                // The receiver only goes away if the render was abandoned.
+                let _ = sender.send(tile);
             });
+            tile_count += 1;
         }
     }
 
-    // wait for threads to execute
-    while jobs_count.load(Ordering::Relaxed) > 0 {}
-
-    for (x, y) in image.coordinates() {
-        let px_color = render_buffer.get_pixel_color(x, y);
-        image.set_pixel(
-            x,
-            y,
-            px!(
-                (256.0 * px_color.x.clamp(0.0, 0.999)) as u32,
-                (256.0 * px_color.y.clamp(0.0, 0.999)) as u32,
-                (256.0 * px_color.z.clamp(0.0, 0.999)) as u32
-            ),
-        );
+    // Every job owns a sender, so the channel closes once they are all done,
+    // including those that panicked before sending their tile.
+    drop(sender);
+    let mut tiles_received = 0;
+    for tile in receiver.iter() {
+        render_buffer.write_tile(&tile, crop.x0, crop.y0);
+        tiles_received += 1;
+    }
+    pool.wait();
+    if tiles_received < tile_count {
+        return Err(format!(
+            "{} of {} tiles failed to render",
+            tile_count - tiles_received,
+            tile_count
+        ));
     }
 
-    Arc::get_mut(&mut profile).unwrap().total_time = before.elapsed();
+    let profile = Profile {
+        total_time: before.elapsed(),
+        total_bounces: total_bounces.load(Ordering::Relaxed),
+        bvh_stats,
+    };
 
+    println!(
+        "BVH build time: {}s",
+        profile.bvh_stats.build_time.as_secs_f64()
+    );
+    println!(
+        "BVH: {} primitives, {} nodes, {} leaves, max depth {}, max leaf size {}",
+        profile.bvh_stats.primitive_count,
+        profile.bvh_stats.node_count,
+        profile.bvh_stats.leaf_count,
+        profile.bvh_stats.max_depth,
+        profile.bvh_stats.max_leaf_size
+    );
+    println!("Threads: {}", pool.thread_count());
     println!("Total time: {}s", profile.total_time.as_secs_f64());
-    println!("Total bounces: {}", total_bounces.load(Ordering::Relaxed));
+    println!("Total bounces: {}", profile.total_bounces);
 
     let total_time_ns = profile.total_time.as_nanos();
     println!(
         "Performance: {}ns/bounce",
-        total_time_ns as f64 / total_bounces.load(Ordering::Relaxed) as f64
+        total_time_ns as f64 / profile.total_bounces as f64
     );
 
-    image.save("render.bmp").unwrap();
-
-    render_buffer
+    Ok((render_buffer, profile))
 }
 
 fn main() {
-    let config = RaytracerConfig {
-        max_threads: 12,
-        tile_size: 64,
-        samples_per_pixel: 512,
-        max_bounces: 8,
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(ParsedArgs::Render(options)) => options,
+        Ok(ParsedArgs::Help) => {
+            println!("{}", usage());
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, usage());
+            process::exit(2);
+        }
+    };
+
+    let scene = match &options.scene {
+        SceneSource::BuiltinRandom => {
+            let mut config = RaytracerConfig::default();
+            options.apply(&mut config);
+            builtin_random_scene(config)
+        }
+        SceneSource::File(path) => match load_scene(path) {
+            Ok(mut scene) => {
+                options.apply(&mut scene.config);
+                scene
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        },
     };
-    start_raytracer(config);
+
+    let config = scene.config;
+    let pool = ThreadPool::new(config.max_threads);
+    let (render_buffer, profile) = match start_raytracer(scene, &pool) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let metadata = ImageMetadata {
+        samples_per_pixel: config.samples_per_pixel,
+        max_bounces: config.max_bounces,
+        seed: config.seed,
+        render_time: profile.total_time,
+    };
+    if let Err(e) = write_image(
+        &render_buffer,
+        &options.output,
+        options.format,
+        &config.tone_mapping,
+        &metadata,
+    ) {
+        eprintln!("error: cannot write {}: {}", options.output.display(), e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(threads: u32, tile_size: u32) -> Vec<Vec3> {
+        let config = RaytracerConfig {
+            film: Film::new(40, 24),
+            max_threads: threads,
+            samples_per_pixel: 2,
+            max_bounces: 4,
+            tile_size,
+            ..RaytracerConfig::default()
+        };
+        let pool = ThreadPool::new(threads);
+        let (render_buffer, _) = start_raytracer(builtin_random_scene(config), &pool)
+            .ok()
+            .unwrap();
+        render_buffer.pixels
+    }
+
+    /// Every pixel draws from its own generator, so neither the number of
+    /// threads nor the order tiles are rendered in may change the image.
+    #[test]
+    fn render_is_independent_of_threads_and_tiles() {
+        let reference = render(1, 64);
+        assert!(render(4, 7) == reference);
+        assert!(render(3, 16) == reference);
+    }
 }
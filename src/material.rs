@@ -1,101 +1,568 @@
+use crate::alpha::*;
 use crate::hittable::*;
-use crate::random::randomf32;
-use crate::ray::*;
+use crate::microfacet::*;
+use crate::random::*;
+use crate::texture::*;
 use crate::vec3::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
 
-#[derive(Clone, Copy)]
-pub enum MaterialType {
-    Lambertian,
-    Metal,
-    Dieletric,
-}
-
-#[derive(Clone, Copy)]
-pub struct Material {
-    pub mat_type: MaterialType,
-    pub albedo: Color,
-    pub fuzz_ir: f32,
-}
-
-#[inline(always)]
-fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
-    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    r0 + (1.0 - r0) * f32::powf(1.0 - cosine, 5.0)
-}
-
-impl Material {
-    pub fn new(mat_type: MaterialType, albedo: Color, fuzz_ir: f32) -> Self {
-        match mat_type {
-            MaterialType::Metal => {
-                let fuzz_ir = if fuzz_ir < 1.0 { fuzz_ir } else { 1.0 };
-                Self {
-                    mat_type,
-                    albedo,
-                    fuzz_ir,
-                }
+/// Index of a material in the `MaterialTable`.
+pub type MaterialId = u32;
+
+/// Direction picked by `Material::sample`.
+pub struct BsdfSample {
+    /// Normalized direction the light arrives from.
+    pub wi: Vec3,
+    /// BSDF times cosine over density: what the path throughput is
+    /// multiplied by.
+    pub weight: Color,
+    /// Solid angle density of `wi`. Meaningless for specular samples.
+    pub pdf: f32,
+    /// Whether `wi` came from a specular lobe that `eval` doesn't describe.
+    pub specular: bool,
+}
+
+/// Surface scattering. All directions are normalized and point away from
+/// the surface: `wo` towards the viewer, `wi` towards the light.
+pub trait Material: Send + Sync {
+    /// Prepares the hit record for the other methods, which is where normal
+    /// and bump maps replace the shading normal. The integrator calls it
+    /// once per hit, before anything else.
+    fn shade(&self, _rec: &mut HitRecord) {}
+
+    /// Picks an incoming direction, or returns `None` if the path ends here.
+    fn sample(&self, wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample>;
+
+    /// BSDF times the cosine term, for the non-specular part of the
+    /// material.
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color;
+
+    /// Solid angle density with which `sample` picks `wi`.
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32;
+
+    /// Radiance emitted from the surface.
+    fn emitted(&self) -> Color {
+        Color::ZERO
+    }
+
+    /// Whether the material only scatters specularly, in which case light
+    /// sampling is pointless.
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+/// Every material of a scene, referenced from shapes and hit records by id.
+pub struct MaterialTable {
+    materials: Vec<Box<dyn Material>>,
+    /// Alpha mask of each material. Intersection tests it rather than
+    /// the material, so it is kept apart.
+    alpha: Vec<Option<AlphaMask>>,
+}
+
+impl MaterialTable {
+    pub fn new() -> Self {
+        Self {
+            materials: vec![],
+            alpha: vec![],
+        }
+    }
+
+    pub fn add(&mut self, material: Box<dyn Material>) -> MaterialId {
+        self.materials.push(material);
+        self.alpha.push(None);
+        (self.materials.len() - 1) as MaterialId
+    }
+
+    /// Cuts the material's surfaces out where `mask` says so, whether their
+    /// shapes were added before or after. Emitters can't be cut out, as
+    /// their lights are sampled over the whole surface.
+    pub fn set_alpha(&mut self, id: MaterialId, mask: AlphaMask) -> Result<(), String> {
+        if !self.materials[id as usize].emitted().near_zero() {
+            return Err("emissive materials can't have an alpha mask".to_string());
+        }
+        self.alpha[id as usize] = Some(mask);
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn alpha(&self, id: MaterialId) -> Option<&AlphaMask> {
+        self.alpha[id as usize].as_ref()
+    }
+
+    #[inline(always)]
+    pub fn get(&self, id: MaterialId) -> &dyn Material {
+        self.materials[id as usize].as_ref()
+    }
+}
+
+pub struct Lambertian {
+    pub albedo: Arc<dyn Texture>,
+}
+
+/// Roughness of metals and glass. A constant keeps the material purely
+/// specular when it is zero; a texture is read through `Texture::scalar`
+/// at every hit.
+#[derive(Clone)]
+pub enum Roughness {
+    Constant(f32),
+    Textured(Arc<dyn Texture>),
+}
+
+impl Roughness {
+    #[inline(always)]
+    fn distribution(&self, rec: &HitRecord) -> TrowbridgeReitz {
+        match self {
+            Roughness::Constant(roughness) => TrowbridgeReitz::from_roughness(*roughness),
+            Roughness::Textured(texture) => TrowbridgeReitz::from_roughness(texture.scalar(rec)),
+        }
+    }
+
+    /// Whether the surface is smooth everywhere.
+    fn is_smooth(&self) -> bool {
+        match self {
+            Roughness::Constant(roughness) => {
+                TrowbridgeReitz::from_roughness(*roughness).is_smooth()
             }
-            _ => Self {
-                mat_type,
-                albedo,
-                fuzz_ir,
-            },
+            Roughness::Textured(_) => false,
+        }
+    }
+}
+
+impl From<f32> for Roughness {
+    fn from(roughness: f32) -> Self {
+        Roughness::Constant(roughness)
+    }
+}
+
+/// Rough metal: GGX microfacet reflection with the Fresnel term of a
+/// complex index of refraction `eta + i k`.
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: Roughness,
+}
+
+/// Glass and other transparent materials, optionally rough.
+pub struct Dieletric {
+    pub ir: f32,
+    pub roughness: Roughness,
+}
+
+/// Light source: emits `emission` from both sides of the surface and
+/// absorbs everything that hits it.
+pub struct Emissive {
+    pub emission: Color,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    #[inline(always)]
+    fn sample(&self, _wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
+
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let wi = scatter_direction.normalized();
+        Some(BsdfSample {
+            wi,
+            weight: self.albedo.value(rec),
+            pdf: f32::max(rec.normal.dot(wi), 0.0) / PI,
+            specular: false,
+        })
+    }
+
+    #[inline(always)]
+    fn eval(&self, _wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        self.albedo.value(rec) * (f32::max(rec.normal.dot(wi), 0.0) / PI)
+    }
+
+    #[inline(always)]
+    fn pdf(&self, _wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32 {
+        f32::max(rec.normal.dot(wi), 0.0) / PI
+    }
+}
+
+/// Measured complex indices of refraction at roughly 650, 550 and 450nm,
+/// as `(eta, k)`.
+pub fn conductor_preset(name: &str) -> Option<(Color, Color)> {
+    match name {
+        "gold" => Some((color!(0.143, 0.374, 1.442), color!(3.983, 2.385, 1.603))),
+        "copper" => Some((color!(0.200, 0.924, 1.102), color!(3.912, 2.452, 2.142))),
+        "aluminium" => Some((color!(1.657, 0.880, 0.521), color!(9.224, 6.270, 4.837))),
+        "silver" => Some((color!(0.155, 0.117, 0.138), color!(4.828, 3.122, 2.147))),
+        _ => None,
+    }
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: impl Into<Roughness>) -> Self {
+        Self {
+            eta,
+            k,
+            roughness: roughness.into(),
+        }
+    }
+
+    /// Conductor that reflects `albedo` at normal incidence, for metals
+    /// without measured data. With eta = 1 the reflectance is
+    /// k^2 / (4 + k^2), which is solved for k.
+    pub fn from_reflectance(albedo: Color, roughness: impl Into<Roughness>) -> Self {
+        let k = |r: f32| {
+            let r = r.clamp(0.0, 0.999);
+            2.0 * f32::sqrt(r / (1.0 - r))
+        };
+        Self::new(
+            color!(1.0),
+            color!(k(albedo.x), k(albedo.y), k(albedo.z)),
+            roughness,
+        )
+    }
+}
+
+impl Material for Conductor {
+    #[inline(always)]
+    fn sample(&self, wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let distribution = self.roughness.distribution(rec);
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if distribution.is_smooth() {
+            return Some(BsdfSample {
+                wi: frame.to_world(vec3!(-wo.x, -wo.y, wo.z)),
+                weight: fresnel_conductor(wo.z, self.eta, self.k),
+                pdf: 0.0,
+                specular: true,
+            });
         }
+
+        let wm = distribution.sample_wm(wo, rng.randomf32(), rng.randomf32());
+        let wi = reflect(wo, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        // D and the cosines cancel against the visible normal density.
+        let f = fresnel_conductor(wo.dot(wm), self.eta, self.k);
+        Some(BsdfSample {
+            wi: frame.to_world(wi),
+            weight: f * (distribution.g(wo, wi) / distribution.g1(wo)),
+            pdf: distribution.visible_d(wo, wm) / (4.0 * wo.dot(wm).abs()),
+            specular: false,
+        })
+    }
+
+    #[inline(always)]
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        let distribution = self.roughness.distribution(rec);
+        if distribution.is_smooth() {
+            return Color::ZERO;
+        }
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::ZERO;
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return Color::ZERO;
+        }
+        let wm = wm.normalized();
+
+        let f = fresnel_conductor(wo.dot(wm), self.eta, self.k);
+        f * (distribution.d(wm) * distribution.g(wo, wi) / (4.0 * wo.z))
+    }
+
+    #[inline(always)]
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32 {
+        let distribution = self.roughness.distribution(rec);
+        if distribution.is_smooth() {
+            return 0.0;
+        }
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = wm.normalized();
+        distribution.visible_d(wo, wm) / (4.0 * wo.dot(wm).abs())
+    }
+
+    fn is_specular(&self) -> bool {
+        self.roughness.is_smooth()
+    }
+}
+
+impl Dieletric {
+    pub fn new(ir: f32, roughness: impl Into<Roughness>) -> Self {
+        Self {
+            ir,
+            roughness: roughness.into(),
+        }
+    }
+
+    /// Relative index of refraction across the surface, seen from the side
+    /// `rec.normal` points to.
+    #[inline(always)]
+    fn eta(&self, rec: &HitRecord) -> f32 {
+        if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+
+    #[inline(always)]
+    fn sample_smooth(
+        &self,
+        wo: Vec3,
+        eta: f32,
+        frame: &Frame,
+        rng: &mut Rng,
+    ) -> Option<BsdfSample> {
+        let r = fresnel_dielectric(wo.z, eta);
+        let (wi, weight) = if rng.randomf32() < r {
+            (vec3!(-wo.x, -wo.y, wo.z), color!(1.0))
+        } else {
+            // Radiance is compressed into a smaller solid angle on the
+            // denser side.
+            let (wi, etap) = refract(wo, vec3!(0, 0, 1), eta)?;
+            (wi, color!(1.0 / (etap * etap)))
+        };
+
+        Some(BsdfSample {
+            wi: frame.to_world(wi),
+            weight,
+            pdf: 0.0,
+            specular: true,
+        })
     }
 
     #[inline(always)]
-    pub fn scatter(
-        self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        match self.mat_type {
-            MaterialType::Lambertian => {
-                let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-
-                if scatter_direction.near_zero() {
-                    scatter_direction = rec.normal;
-                }
-
-                *scattered = Ray::new(rec.p, scatter_direction);
-                *attenuation = self.albedo;
-                true
+    fn sample_rough(
+        &self,
+        wo: Vec3,
+        eta: f32,
+        distribution: &TrowbridgeReitz,
+        frame: &Frame,
+        rng: &mut Rng,
+    ) -> Option<BsdfSample> {
+        let wm = distribution.sample_wm(wo, rng.randomf32(), rng.randomf32());
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        let t = 1.0 - r;
+
+        let (wi, f, pdf) = if rng.randomf32() < r {
+            let wi = reflect(wo, wm);
+            if wo.z * wi.z <= 0.0 {
+                return None;
             }
-            MaterialType::Metal => {
-                let reflected = r_in.direction.normalized().reflect(rec.normal);
-                *scattered = Ray::new(
-                    rec.p,
-                    reflected + self.fuzz_ir * Vec3::random_in_unit_sphere(),
-                );
-                *attenuation = self.albedo;
-                scattered.direction.dot(rec.normal) > 0.0
+            let pdf = distribution.visible_d(wo, wm) / (4.0 * wo.dot(wm).abs()) * r;
+            let f = distribution.d(wm) * distribution.g(wo, wi) * r / (4.0 * wi.z * wo.z).abs();
+            (wi, f, pdf)
+        } else {
+            let (wi, etap) = refract(wo, wm, eta)?;
+            if wo.z * wi.z >= 0.0 {
+                return None;
             }
-            MaterialType::Dieletric => {
-                *attenuation = color!(1.0);
-                let refraction_ratio = if rec.front_face {
-                    1.0 / self.fuzz_ir
-                } else {
-                    self.fuzz_ir
-                };
-
-                let unit_direction = r_in.direction.normalized();
-                let cos_theta = f32::min((-unit_direction).dot(rec.normal), 1.0);
-                let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
-
-                let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
-
-                let direction =
-                    if cannot_refract || reflectance(cos_theta, refraction_ratio) > randomf32() {
-                        unit_direction.reflect(rec.normal)
-                    } else {
-                        unit_direction.refract(rec.normal, refraction_ratio)
-                    };
-
-                *scattered = Ray::new(rec.p, direction);
-                true
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            let denom = denom * denom;
+            let pdf = distribution.visible_d(wo, wm) * wi.dot(wm).abs() / denom * t;
+            let f = t
+                * distribution.d(wm)
+                * distribution.g(wo, wi)
+                * (wi.dot(wm) * wo.dot(wm) / (wi.z * wo.z * denom)).abs()
+                / (etap * etap);
+            (wi, f, pdf)
+        };
+
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi: frame.to_world(wi),
+            weight: color!(f * wi.z.abs() / pdf),
+            pdf,
+            specular: false,
+        })
+    }
+}
+
+impl Material for Dieletric {
+    #[inline(always)]
+    fn sample(&self, wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let distribution = self.roughness.distribution(rec);
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let eta = self.eta(rec);
+        if distribution.is_smooth() {
+            self.sample_smooth(wo, eta, &frame, rng)
+        } else {
+            self.sample_rough(wo, eta, &distribution, &frame, rng)
+        }
+    }
+
+    #[inline(always)]
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        let distribution = self.roughness.distribution(rec);
+        if distribution.is_smooth() {
+            return Color::ZERO;
+        }
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        let eta = self.eta(rec);
+        let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+            return Color::ZERO;
+        };
+
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        let f = if etap == 1.0 {
+            distribution.d(wm) * distribution.g(wo, wi) * r / (4.0 * wi.z * wo.z).abs()
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            let denom = denom * denom * wi.z * wo.z;
+            (1.0 - r)
+                * distribution.d(wm)
+                * distribution.g(wo, wi)
+                * (wi.dot(wm) * wo.dot(wm) / denom).abs()
+                / (etap * etap)
+        };
+        color!(f * wi.z.abs())
+    }
+
+    #[inline(always)]
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32 {
+        let distribution = self.roughness.distribution(rec);
+        if distribution.is_smooth() {
+            return 0.0;
+        }
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        let eta = self.eta(rec);
+        let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        if etap == 1.0 {
+            distribution.visible_d(wo, wm) / (4.0 * wo.dot(wm).abs()) * r
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            distribution.visible_d(wo, wm) * wi.dot(wm).abs() / (denom * denom) * (1.0 - r)
+        }
+    }
+
+    fn is_specular(&self) -> bool {
+        self.roughness.is_smooth()
+    }
+}
+
+impl Emissive {
+    pub fn new(color: Color, strength: f32) -> Self {
+        Self {
+            emission: color * strength,
+        }
+    }
+}
+
+impl Material for Emissive {
+    fn sample(&self, _wo: Vec3, _rec: &HitRecord, _rng: &mut Rng) -> Option<BsdfSample> {
+        None
+    }
+
+    fn eval(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> Color {
+        Color::ZERO
+    }
+
+    fn pdf(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> f32 {
+        0.0
+    }
+
+    fn emitted(&self) -> Color {
+        self.emission
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(front_face: bool) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.normal = vec3!(0, 0, 1);
+        rec.geometric_normal = rec.normal;
+        rec.dpdu = vec3!(1, 0, 0);
+        rec.front_face = front_face;
+        rec
+    }
+
+    /// Checks f(wo, wi) == f(wi, wo) for reflection, undoing the cosine
+    /// that `eval` includes.
+    fn check_reciprocity(material: &dyn Material, rec: &HitRecord) {
+        let mut rng = Rng::new(19);
+        for _ in 0..1000 {
+            let wo = Vec3::random_unit_vector(&mut rng);
+            let wi = Vec3::random_unit_vector(&mut rng);
+            let (wo, wi) = (vec3!(wo.x, wo.y, wo.z.abs()), vec3!(wi.x, wi.y, wi.z.abs()));
+            let forward = material.eval(wo, wi, rec) / wi.z;
+            let backward = material.eval(wi, wo, rec) / wo.z;
+            for i in 0..3 {
+                assert!((forward[i] - backward[i]).abs() <= 1e-3 * forward[i].max(1e-3));
             }
         }
     }
+
+    #[test]
+    fn microfacet_reflection_is_reciprocal() {
+        let (eta, k) = conductor_preset("gold").unwrap();
+        check_reciprocity(&Conductor::new(eta, k, 0.4), &hit(true));
+
+        let glass = Dieletric::new(1.5, 0.3);
+        check_reciprocity(&glass, &hit(true));
+        check_reciprocity(&glass, &hit(false));
+    }
+
+    #[test]
+    fn emitters_reject_alpha_masks() {
+        let mut table = MaterialTable::new();
+        let lamp = table.add(Box::new(Emissive::new(color!(1.0), 4.0)));
+        let leaf = table.add(Box::new(Lambertian::new(color!(0.2, 0.5, 0.1))));
+        let alpha: Arc<dyn Texture> = Arc::new(SolidColor::new(color!(0.5)));
+        let mask = || AlphaMask::new(Arc::clone(&alpha), AlphaMode::Threshold, 0.5);
+
+        assert!(table.set_alpha(lamp, mask()).is_err());
+        assert!(table.alpha(lamp).is_none());
+        assert!(table.set_alpha(leaf, mask()).is_ok());
+        assert!(table.alpha(leaf).is_some());
+    }
 }
@@ -0,0 +1,326 @@
+use crate::vec3::*;
+use std::f32::consts::PI;
+
+/// Orthonormal frame around a surface normal. BSDFs work in this local
+/// space, where the normal is +z.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    #[inline(always)]
+    pub fn new(n: Vec3) -> Self {
+        let (s, t) = n.orthonormal_basis();
+        Self { s, t, n }
+    }
+
+    /// Frame whose first tangent is `tangent` made orthogonal to `n`. Falls
+    /// back to an arbitrary tangent when `tangent` is zero or parallel to `n`.
+    #[inline(always)]
+    pub fn from_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let s = tangent - n * n.dot(tangent);
+        if s.lensq() <= 1e-12 * tangent.lensq() {
+            return Self::new(n);
+        }
+        let s = s.normalized();
+        Self {
+            s,
+            t: n.cross(s),
+            n,
+        }
+    }
+
+    #[inline(always)]
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        vec3!(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
+
+    #[inline(always)]
+    pub fn to_world(self, v: Vec3) -> Vec3 {
+        v.x * self.s + v.y * self.t + v.z * self.n
+    }
+}
+
+/// Below this alpha the distribution is treated as a perfect mirror; the
+/// GGX math breaks down numerically long before alpha reaches zero.
+const SMOOTH_ALPHA: f32 = 1e-3;
+
+/// GGX / Trowbridge-Reitz microfacet distribution, stretched by
+/// `alpha_x` and `alpha_y` along the frame's tangents. Directions are in
+/// the local shading frame.
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    /// Perceptual roughness in [0, 1] is squared to get alpha, which makes
+    /// the parameter behave roughly linearly.
+    pub fn from_roughness(roughness: f32) -> Self {
+        Self::anisotropic(roughness, 0.0)
+    }
+
+    /// Disney's mapping: `anisotropic` in [0, 1] stretches the highlight
+    /// along the first tangent while keeping its area roughly constant.
+    pub fn anisotropic(roughness: f32, anisotropic: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let aspect = f32::sqrt(1.0 - 0.9 * anisotropic.clamp(0.0, 1.0));
+        let alpha = roughness * roughness;
+        Self {
+            alpha_x: alpha / aspect,
+            alpha_y: alpha * aspect,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_smooth(&self) -> bool {
+        f32::max(self.alpha_x, self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals.
+    #[inline(always)]
+    pub fn d(&self, wm: Vec3) -> f32 {
+        let cos2 = wm.z * wm.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let x = wm.x / self.alpha_x;
+        let y = wm.y / self.alpha_y;
+        let e = 1.0 + (x * x + y * y) / cos2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2 * cos2 * e * e)
+    }
+
+    /// Smith's auxiliary function: the projected area of microfacets that
+    /// are shadowed from `w`, relative to the visible area.
+    #[inline(always)]
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let x = w.x * self.alpha_x;
+        let y = w.y * self.alpha_y;
+        (f32::sqrt(1.0 + (x * x + y * y) / cos2) - 1.0) / 2.0
+    }
+
+    #[inline(always)]
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing.
+    #[inline(always)]
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `w`, which is what `sample_wm`
+    /// draws from.
+    #[inline(always)]
+    pub fn visible_d(&self, w: Vec3, wm: Vec3) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// Samples a normal visible from `w` (Heitz 2018).
+    #[inline(always)]
+    pub fn sample_wm(&self, w: Vec3, u0: f32, u1: f32) -> Vec3 {
+        // Stretch to the hemisphere configuration.
+        let mut wh = vec3!(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalized();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z < 0.99999 {
+            vec3!(0, 0, 1).cross(wh).normalized()
+        } else {
+            vec3!(1, 0, 0)
+        };
+        let t2 = wh.cross(t1);
+
+        // Uniform point on the disk, warped towards the visible half.
+        let r = f32::sqrt(u0);
+        let phi = 2.0 * PI * u1;
+        let px = r * phi.cos();
+        let mut py = r * phi.sin();
+        let h = f32::sqrt(1.0 - px * px);
+        let s = (1.0 + wh.z) / 2.0;
+        py = (1.0 - s) * h + s * py;
+
+        let pz = f32::sqrt(f32::max(0.0, 1.0 - px * px - py * py));
+        let nh = px * t1 + py * t2 + pz * wh;
+        vec3!(
+            self.alpha_x * nh.x,
+            self.alpha_y * nh.y,
+            f32::max(1e-6, nh.z)
+        )
+        .normalized()
+    }
+}
+
+#[inline(always)]
+pub fn reflect(wo: Vec3, n: Vec3) -> Vec3 {
+    -wo + 2.0 * wo.dot(n) * n
+}
+
+/// Refracts `wi` through a surface with normal `n` and relative index of
+/// refraction `eta` (inside over outside, with `n` pointing outside).
+/// Returns the refracted direction and the relative index along the path,
+/// or `None` on total internal reflection.
+#[inline(always)]
+pub fn refract(wi: Vec3, mut n: Vec3, mut eta: f32) -> Option<(Vec3, f32)> {
+    let mut cos_i = n.dot(wi);
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+        n = -n;
+    }
+
+    let sin2_i = f32::max(0.0, 1.0 - cos_i * cos_i);
+    let sin2_t = sin2_i / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    Some((-wi / eta + (cos_i / eta - cos_t) * n, eta))
+}
+
+/// Microfacet normal that scatters `wo` into `wi` across an interface with
+/// relative index `eta`, oriented towards +z, along with the relative index
+/// along the path (1 for reflection). `None` for configurations no
+/// microfacet can produce.
+#[inline(always)]
+pub fn generalized_half_vector(wo: Vec3, wi: Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    let cos_o = wo.z;
+    let cos_i = wi.z;
+    if cos_i == 0.0 || cos_o == 0.0 {
+        return None;
+    }
+
+    let reflect = cos_i * cos_o > 0.0;
+    let etap = match (reflect, cos_o > 0.0) {
+        (true, _) => 1.0,
+        (false, true) => eta,
+        (false, false) => 1.0 / eta,
+    };
+
+    let wm = wi * etap + wo;
+    if wm.near_zero() {
+        return None;
+    }
+    let mut wm = wm.normalized();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+
+    // Microfacets facing away from either direction contribute nothing.
+    if wm.dot(wi) * cos_i < 0.0 || wm.dot(wo) * cos_o < 0.0 {
+        return None;
+    }
+    Some((wm, etap))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface. `cos_i` is
+/// negative for light arriving from the inside.
+#[inline(always)]
+pub fn fresnel_dielectric(cos_i: f32, mut eta: f32) -> f32 {
+    let mut cos_i = cos_i.clamp(-1.0, 1.0);
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of
+/// refraction `eta + i k`, for one wavelength.
+#[inline(always)]
+fn fresnel_complex(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = f32::sqrt(f32::max(0.0, t0 * t0 + 4.0 * eta * eta * k * k));
+    let t1 = a2b2 + cos2;
+    let a = f32::sqrt(f32::max(0.0, 0.5 * (a2b2 + t0)));
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (rp + rs) / 2.0
+}
+
+#[inline(always)]
+pub fn fresnel_conductor(cos_i: f32, eta: Color, k: Color) -> Color {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    color!(
+        fresnel_complex(cos_i, eta.x, k.x),
+        fresnel_complex(cos_i, eta.y, k.y),
+        fresnel_complex(cos_i, eta.z, k.z)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::*;
+
+    #[test]
+    fn sample_wm_follows_visible_d() {
+        let mut rng = Rng::new(17);
+        let w = vec3!(0.6, 0, 0.8);
+        for distribution in [
+            TrowbridgeReitz::from_roughness(0.7),
+            TrowbridgeReitz::anisotropic(0.6, 0.8),
+        ] {
+            // Weighting each sample by its inverse density estimates the
+            // solid angle of a cap around +z whose normals are all visible
+            // from `w`.
+            let cos_cap = 0.9;
+            let n = 200_000;
+            let mut estimate = 0.0;
+            for _ in 0..n {
+                let wm = distribution.sample_wm(w, rng.randomf32(), rng.randomf32());
+                assert!((wm.len() - 1.0).abs() < 1e-4 && wm.z > 0.0);
+                if wm.z > cos_cap {
+                    estimate += 1.0 / distribution.visible_d(w, wm);
+                }
+            }
+            estimate /= n as f32;
+
+            let solid_angle = 2.0 * PI * (1.0 - cos_cap);
+            assert!((estimate - solid_angle).abs() < 0.02 * solid_angle);
+        }
+    }
+
+    #[test]
+    fn fresnel_dielectric_limits() {
+        // Normal incidence, from either side.
+        let f0 = ((1.5f32 - 1.0) / (1.5 + 1.0)).powi(2);
+        assert!((fresnel_dielectric(1.0, 1.5) - f0).abs() < 1e-6);
+        assert!((fresnel_dielectric(-1.0, 1.5) - f0).abs() < 1e-6);
+
+        // Past the critical angle inside the denser medium.
+        assert!(fresnel_dielectric(-0.2, 1.5) == 1.0);
+        assert!(fresnel_dielectric(0.2, 1.0 / 1.5) == 1.0);
+        assert!(fresnel_dielectric(0.8, 1.0 / 1.5) < 1.0);
+    }
+}
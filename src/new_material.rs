@@ -1,115 +0,0 @@
-use crate::hittable::*;
-use crate::ray::*;
-use crate::vec3::*;
-
-pub struct Lambertian {
-    pub albedo: Color,
-}
-
-pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f32,
-}
-
-pub struct Dieletric {
-    pub ir: f32,
-}
-
-enum MaterialType {
-    Lambertian(Lambertian),
-    Metal(Metal),
-    Dieletric(Dieletric),
-}
-
-pub trait Material {
-    fn scatter(
-        self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
-}
-
-impl Lambertian {
-    pub fn new(albedo: Color) -> Self {
-        Self { albedo }
-    }
-}
-
-impl Material for Lambertian {
-    fn scatter(
-        self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-
-        *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
-        true
-    }
-}
-
-impl Metal {
-    pub fn new(albedo: Color, fuzz: f32) -> Self {
-        Self { albedo }
-    }
-}
-
-impl Material for Metal {
-    fn scatter(
-        self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = r_in.direction.normalized().reflect(rec.normal);
-        *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
-        *attenuation = self.albedo;
-        scattered.direction.dot(rec.normal) > 0.0
-    }
-}
-
-fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
-    let cos_theta = ((-uv).dot(n)).min(1.0);
-    let r_out_perp = etai_over_etat * (uv + cos_theta * n);
-    let r_out_parallel = -((1.0 - r_out_perp.lensq()).abs().sqrt()) * n;
-    r_out_perp + r_out_parallel
-}
-
-impl Dieletric {
-    pub fn new(ir: f32) -> Self {
-        Self { ir }
-    }
-}
-
-impl Material for Dieletric {
-    fn scatter(
-        self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = color!(1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
-
-        let unit_direction = r_in.direction.normalized();
-        let refracted = refract(unit_direction, rec.normal, refraction_ratio);
-
-        *scattered = Ray::new(rec.p, refracted);
-        true
-    }
-}
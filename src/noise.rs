@@ -0,0 +1,295 @@
+use crate::random::*;
+use crate::vec3::*;
+
+/// Which gradient noise fBm and turbulence are built from.
+#[derive(Clone, Copy)]
+pub enum NoiseBasis {
+    Perlin,
+    Simplex,
+}
+
+impl NoiseBasis {
+    pub const NAMES: &'static str = "perlin, simplex";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "perlin" => Some(Self::Perlin),
+            "simplex" => Some(Self::Simplex),
+            _ => None,
+        }
+    }
+}
+
+/// Seeded noise generator. The permutation table is shuffled from its own
+/// generator, so the same seed always gives the same noise no matter what
+/// else is random in the render.
+pub struct Noise {
+    /// Permutation of 0..256, repeated so lookups never need wrapping.
+    perm: [u8; 512],
+}
+
+/// Edge midpoints of a cube, the gradients of simplex noise.
+const GRAD3: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+#[inline(always)]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline(always)]
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Dot product of one of twelve gradient directions, picked by `hash`, with
+/// the offset `(x, y, z)`.
+#[inline(always)]
+fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut table = [0u8; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        for i in (1..256).rev() {
+            let j = ((rng.randomf32() * (i + 1) as f32) as usize).min(i);
+            table.swap(i, j);
+        }
+
+        let mut perm = [0u8; 512];
+        for (i, v) in perm.iter_mut().enumerate() {
+            *v = table[i & 255];
+        }
+        Self { perm }
+    }
+
+    #[inline(always)]
+    fn hash(&self, x: i32, y: i32, z: i32) -> u8 {
+        let p = &self.perm;
+        p[p[p[(x & 255) as usize] as usize + (y & 255) as usize] as usize + (z & 255) as usize]
+    }
+
+    /// Improved Perlin noise (Perlin 2002), roughly in [-1, 1].
+    pub fn perlin(&self, p: Vec3) -> f32 {
+        let (xi, yi, zi) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (x, y, z) = (p.x - xi, p.y - yi, p.z - zi);
+        let (xi, yi, zi) = (xi as i32, yi as i32, zi as i32);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let g = |dx: i32, dy: i32, dz: i32| {
+            grad(
+                self.hash(xi + dx, yi + dy, zi + dz),
+                x - dx as f32,
+                y - dy as f32,
+                z - dz as f32,
+            )
+        };
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, g(0, 0, 0), g(1, 0, 0)),
+                lerp(u, g(0, 1, 0), g(1, 1, 0)),
+            ),
+            lerp(
+                v,
+                lerp(u, g(0, 0, 1), g(1, 0, 1)),
+                lerp(u, g(0, 1, 1), g(1, 1, 1)),
+            ),
+        )
+    }
+
+    /// 3D simplex noise (Perlin 2001, after Gustavson's description),
+    /// roughly in [-1, 1]. Cheaper than Perlin noise and without its
+    /// axis-aligned artifacts.
+    pub fn simplex(&self, p: Vec3) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+
+        // Skew into the simplex grid to find the cell.
+        let s = (p.x + p.y + p.z) * F3;
+        let i = (p.x + s).floor();
+        let j = (p.y + s).floor();
+        let k = (p.z + s).floor();
+        let t = (i + j + k) * G3;
+        let d0 = vec3!(p.x - (i - t), p.y - (j - t), p.z - (k - t));
+
+        // Which of the six tetrahedra of the cell the point is in.
+        let (o1, o2) = if d0.x >= d0.y {
+            if d0.y >= d0.z {
+                ([1, 0, 0], [1, 1, 0])
+            } else if d0.x >= d0.z {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if d0.y < d0.z {
+            ([0, 0, 1], [0, 1, 1])
+        } else if d0.x < d0.z {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+
+        let (i, j, k) = (i as i32, j as i32, k as i32);
+        let corners = [
+            ([0, 0, 0], d0),
+            (o1, d0 - vec3!(o1[0], o1[1], o1[2]) + vec3!(G3)),
+            (o2, d0 - vec3!(o2[0], o2[1], o2[2]) + vec3!(2.0 * G3)),
+            ([1, 1, 1], d0 - vec3!(1.0 - 3.0 * G3)),
+        ];
+
+        let mut n = 0.0;
+        for (o, d) in corners {
+            let t = 0.6 - d.lensq();
+            if t > 0.0 {
+                let g = GRAD3[self.hash(i + o[0], j + o[1], k + o[2]) as usize % 12];
+                let t2 = t * t;
+                n += t2 * t2 * (g[0] * d.x + g[1] * d.y + g[2] * d.z);
+            }
+        }
+        32.0 * n
+    }
+
+    #[inline(always)]
+    pub fn basis(&self, basis: NoiseBasis, p: Vec3) -> f32 {
+        match basis {
+            NoiseBasis::Perlin => self.perlin(p),
+            NoiseBasis::Simplex => self.simplex(p),
+        }
+    }
+
+    /// Fractional Brownian motion: octaves of noise at doubling frequency
+    /// and halving amplitude, normalized back to roughly [-1, 1].
+    pub fn fbm(&self, basis: NoiseBasis, p: Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut p = p;
+        for _ in 0..octaves {
+            sum += amplitude * self.basis(basis, p);
+            total += amplitude;
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Like `fbm` but summing absolute values, which gives the creases of
+    /// flames and marble veins. Roughly in [0, 1].
+    pub fn turbulence(&self, basis: NoiseBasis, p: Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut p = p;
+        for _ in 0..octaves {
+            sum += amplitude * self.basis(basis, p).abs();
+            total += amplitude;
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Worley (cellular) noise with one feature point per unit cell:
+    /// distances to the nearest and second nearest feature points.
+    pub fn worley(&self, p: Vec3) -> (f32, f32) {
+        let (xi, yi, zi) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let mut f1 = f32::MAX;
+        let mut f2 = f32::MAX;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (x, y, z) = (xi + dx, yi + dy, zi + dz);
+                    // Each component of the point's offset takes one more
+                    // round of the table.
+                    let h = self.perm[self.hash(x, y, z) as usize] as usize;
+                    let offset = vec3!(
+                        self.perm[h] as f32 + 0.5,
+                        self.perm[h + 1] as f32 + 0.5,
+                        self.perm[h + 2] as f32 + 0.5
+                    ) / 256.0;
+                    let feature = vec3!(x, y, z) + offset;
+                    let d = (feature - p).len();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<Vec3> {
+        let mut rng = Rng::new(29);
+        (0..500)
+            .map(|_| Vec3::random_range(&mut rng, -50.0, 50.0))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_noise() {
+        let (a, b, c) = (Noise::new(42), Noise::new(42), Noise::new(43));
+        let mut differs = false;
+        for p in points() {
+            assert_eq!(a.perlin(p), b.perlin(p));
+            assert_eq!(a.simplex(p), b.simplex(p));
+            assert_eq!(a.worley(p), b.worley(p));
+            differs |= a.perlin(p) != c.perlin(p);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        let noise = Noise::new(7);
+        for p in points() {
+            let (f1, f2) = noise.worley(p);
+            // The point's own cell always holds a feature point.
+            assert!(0.0 <= f1 && f1 <= f2 && f1 <= 3f32.sqrt());
+        }
+    }
+}
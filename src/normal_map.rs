@@ -0,0 +1,158 @@
+use crate::hittable::*;
+use crate::material::*;
+use crate::random::*;
+use crate::texture::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Step in u and v for the finite differences of bump maps. Hit records
+/// carry no ray differentials, so it is fixed: small enough for fine detail
+/// but well above float precision.
+const BUMP_DELTA: f32 = 5e-4;
+
+/// How a material's shading normal is perturbed.
+pub enum NormalPerturbation {
+    /// Tangent space normal map: red, green and blue hold the normal's
+    /// components along dp/du, dp/dv and the surface normal, mapped from
+    /// [-1, 1] to [0, 1]. The texture has to be read as linear data.
+    NormalMap(Arc<dyn Texture>),
+    /// Height field along the normal, `scale` world units per unit of the
+    /// texture's scalar value.
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: f32,
+    },
+}
+
+/// Any material, shaded with a normal from a normal or bump map. The
+/// geometric normal is left alone, so the integrator still knows which
+/// side of the surface a direction is on.
+pub struct NormalMapped {
+    pub material: Box<dyn Material>,
+    pub perturbation: NormalPerturbation,
+}
+
+impl NormalMapped {
+    pub fn new(material: Box<dyn Material>, perturbation: NormalPerturbation) -> Self {
+        Self {
+            material,
+            perturbation,
+        }
+    }
+
+    #[inline(always)]
+    fn perturbed_normal(&self, rec: &HitRecord) -> Vec3 {
+        match &self.perturbation {
+            NormalPerturbation::NormalMap(texture) => {
+                let c = texture.value(rec) * 2.0 - color!(1.0);
+                let frame = rec.shading_frame();
+                // Mirrored UVs and back faces flip the bitangent.
+                let t = if frame.t.dot(rec.dpdv) < 0.0 {
+                    -frame.t
+                } else {
+                    frame.t
+                };
+                c.x * frame.s + c.y * t + c.z * frame.n
+            }
+            NormalPerturbation::Bump { height, scale } => {
+                let height_at = |du: f32, dv: f32| {
+                    let mut shifted = *rec;
+                    shifted.p = rec.p + du * rec.dpdu + dv * rec.dpdv;
+                    shifted.u = rec.u + du;
+                    shifted.v = rec.v + dv;
+                    scale * height.scalar(&shifted)
+                };
+                let h = height_at(0.0, 0.0);
+                let dhdu = (height_at(BUMP_DELTA, 0.0) - h) / BUMP_DELTA;
+                let dhdv = (height_at(0.0, BUMP_DELTA) - h) / BUMP_DELTA;
+
+                // Derivatives of the displaced surface, ignoring the change
+                // of the normal itself, which is small for shallow bumps.
+                let dpdu = rec.dpdu + dhdu * rec.normal;
+                let dpdv = rec.dpdv + dhdv * rec.normal;
+                dpdu.cross(dpdv)
+            }
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    #[inline(always)]
+    fn shade(&self, rec: &mut HitRecord) {
+        self.material.shade(rec);
+        let normal = self.perturbed_normal(rec);
+        if normal.near_zero() {
+            return;
+        }
+        let normal = normal.normalized();
+        rec.normal = if normal.dot(rec.normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+    }
+
+    #[inline(always)]
+    fn sample(&self, wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        self.material.sample(wo, rec, rng)
+    }
+
+    #[inline(always)]
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        self.material.eval(wo, wi, rec)
+    }
+
+    #[inline(always)]
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32 {
+        self.material.pdf(wo, wi, rec)
+    }
+
+    #[inline(always)]
+    fn emitted(&self) -> Color {
+        self.material.emitted()
+    }
+
+    #[inline(always)]
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that shading with `perturbation` keeps the normal of random
+    /// surfaces, whichever way their tangents and faces are oriented.
+    fn check_unchanged(perturbation: NormalPerturbation) {
+        let material = NormalMapped::new(Box::new(Lambertian::new(color!(0.5))), perturbation);
+        let mut rng = Rng::new(31);
+        for i in 0..200 {
+            let normal = Vec3::random_unit_vector(&mut rng);
+            let (s, t) = normal.orthonormal_basis();
+            let mut rec = HitRecord::new();
+            rec.normal = if i % 2 == 0 { normal } else { -normal };
+            rec.geometric_normal = rec.normal;
+            rec.front_face = i % 2 == 0;
+            rec.dpdu = 2.0 * s;
+            rec.dpdv = if i % 3 == 0 { -t } else { t };
+            rec.u = rng.randomf32();
+            rec.v = rng.randomf32();
+
+            let expected = rec.normal;
+            material.shade(&mut rec);
+            assert!((rec.normal - expected).len() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn flat_maps_leave_the_normal_unchanged() {
+        check_unchanged(NormalPerturbation::NormalMap(Arc::new(SolidColor::new(
+            color!(0.5, 0.5, 1.0),
+        ))));
+        check_unchanged(NormalPerturbation::Bump {
+            height: Arc::new(SolidColor::new(color!(0.3))),
+            scale: 2.0,
+        });
+    }
+}
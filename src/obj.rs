@@ -0,0 +1,890 @@
+use crate::alpha::*;
+use crate::image::*;
+use crate::material::*;
+use crate::normal_map::*;
+use crate::principled::*;
+use crate::texture::*;
+use crate::triangle_mesh::*;
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Material description as found in an MTL file, before it is mapped onto
+/// one of the renderer's material types.
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub kd: Color,
+    pub ks: Color,
+    pub ke: Color,
+    pub tf: Color,
+    pub ns: f32,
+    pub ni: f32,
+    pub d: f32,
+    pub illum: u32,
+    // PBR extension (Pr, Pm, Ps, Pc, Pcr, aniso).
+    pub pr: Option<f32>,
+    pub pm: Option<f32>,
+    pub ps: f32,
+    pub pc: f32,
+    pub pcr: f32,
+    pub aniso: f32,
+    /// Tangent space normal map (`norm`).
+    pub normal_map: Option<Arc<dyn Texture>>,
+    /// Height map (`bump` or `map_Bump`) and its `-bm` multiplier.
+    pub bump: Option<(Arc<dyn Texture>, f32)>,
+    /// Opacity map (`map_d`), cutting the surface out below one half.
+    /// Ignored on emissive materials, whose lights are sampled over the
+    /// whole surface.
+    pub alpha: Option<Arc<dyn Texture>>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kd: color!(0.8),
+            ks: color!(0.0),
+            ke: color!(0.0),
+            tf: color!(1.0),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            pr: None,
+            pm: None,
+            ps: 0.0,
+            pc: 0.0,
+            pcr: 0.0,
+            aniso: 0.0,
+            normal_map: None,
+            bump: None,
+            alpha: None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_emissive(&self) -> bool {
+        !self.ke.near_zero()
+    }
+
+    /// Index of refraction from Ni. Exporters write 0 when they have no
+    /// value, which means the default here; anything else is kept within
+    /// the range of real materials.
+    #[inline(always)]
+    pub fn ior(&self) -> f32 {
+        if self.ni > 0.0 {
+            self.ni.clamp(1.0, 3.0)
+        } else {
+            1.5
+        }
+    }
+
+    /// Maps the MTL material onto a principled one. Anything with an
+    /// emissive color is a light. The PBR extension keys are used where
+    /// present; otherwise refractive illumination models become glass,
+    /// reflective ones metal with Ks as the color, and the Phong exponent is
+    /// converted to roughness. A normal map wins over a bump map. Values
+    /// outside their range (a negative Ns, d or Tr outside [0, 1], ...)
+    /// are clamped, as exporters are not always careful with them.
+    pub fn to_material(&self) -> Box<dyn Material> {
+        if self.is_emissive() {
+            return Box::new(Emissive::new(self.ke, 1.0));
+        }
+
+        let refractive = matches!(self.illum, 4 | 6 | 7 | 9);
+        let reflective = matches!(self.illum, 3 | 5 | 8);
+
+        // Matches the Phong lobe's width; roughness is sqrt(alpha).
+        let roughness = self
+            .pr
+            .unwrap_or_else(|| f32::sqrt(f32::sqrt(2.0 / (self.ns.max(0.0) + 2.0))))
+            .clamp(0.0, 1.0);
+        let metallic = self
+            .pm
+            .unwrap_or(if reflective && !refractive { 1.0 } else { 0.0 })
+            .clamp(0.0, 1.0);
+        let transmission = if refractive {
+            1.0
+        } else {
+            1.0 - self.d.clamp(0.0, 1.0)
+        };
+
+        let base_color = if refractive {
+            self.tf
+        } else if reflective && self.pm.is_none() {
+            self.ks
+        } else {
+            self.kd
+        };
+
+        // Plain diffuse materials have no specular highlight at all.
+        let specular = if self.ks.near_zero() && self.pr.is_none() {
+            0.0
+        } else {
+            0.5
+        };
+
+        let material = Box::new(Principled {
+            base_color: Arc::new(SolidColor::new(base_color)),
+            metallic,
+            roughness: Arc::new(SolidColor::new(color!(roughness))),
+            specular,
+            anisotropic: self.aniso.clamp(0.0, 1.0),
+            sheen: self.ps.clamp(0.0, 1.0),
+            clearcoat: self.pc.clamp(0.0, 1.0),
+            clearcoat_gloss: 1.0 - self.pcr.clamp(0.0, 1.0),
+            transmission,
+            ior: self.ior(),
+            ..Principled::default()
+        });
+
+        let perturbation = match (&self.normal_map, &self.bump) {
+            (Some(texture), _) => NormalPerturbation::NormalMap(Arc::clone(texture)),
+            (None, Some((height, scale))) => NormalPerturbation::Bump {
+                height: Arc::clone(height),
+                scale: *scale,
+            },
+            (None, None) => return material,
+        };
+        Box::new(NormalMapped::new(material, perturbation))
+    }
+}
+
+/// Vertex reference of a face: indices into the position, UV and normal
+/// buffers, already resolved to 0-based.
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    p: u32,
+    uv: Option<u32>,
+    n: Option<u32>,
+}
+
+struct MeshBuilder {
+    material: MaterialId,
+    faces: Vec<[FaceVertex; 3]>,
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError {
+            file: self.file.to_path_buf(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn float(&self, token: Option<&str>, what: &str) -> Result<f32, ObjError> {
+        let token = token.ok_or_else(|| self.error(format!("missing {}", what)))?;
+        token
+            .parse::<f32>()
+            .map_err(|_| self.error(format!("invalid {} '{}'", what, token)))
+    }
+
+    fn vec3<'t>(&self, tokens: &mut impl Iterator<Item = &'t str>) -> Result<Vec3, ObjError> {
+        let x = self.float(tokens.next(), "x component")?;
+        let y = self.float(tokens.next(), "y component")?;
+        let z = self.float(tokens.next(), "z component")?;
+        Ok(vec3!(x, y, z))
+    }
+
+    fn color<'t>(&self, tokens: &mut impl Iterator<Item = &'t str>) -> Result<Color, ObjError> {
+        let r = self.float(tokens.next(), "red component")?;
+        // A single value is a grey level.
+        let g = match tokens.next() {
+            Some(t) => self.float(Some(t), "green component")?,
+            None => return Ok(color!(r)),
+        };
+        let b = self.float(tokens.next(), "blue component")?;
+        Ok(color!(r, g, b))
+    }
+
+    /// Resolves a 1-based (or negative, relative to the end) OBJ index.
+    fn index(&self, token: &str, count: usize, what: &str) -> Result<u32, ObjError> {
+        let i: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", what, token)))?;
+        let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+        if i == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range (have {})",
+                what, i, count
+            )));
+        }
+        Ok(resolved as u32)
+    }
+}
+
+/// Parses the arguments of a texture map statement (`map_Bump -bm 0.5
+/// bumps.png`) into the image file and the `-bm` multiplier. Other options
+/// are skipped.
+fn map_statement(parser: &Parser, rest: &str) -> Result<(String, f32), ObjError> {
+    let mut tokens = rest.split_whitespace().peekable();
+    let mut multiplier = 1.0;
+    while let Some(&option) = tokens.peek() {
+        if !option.starts_with('-') {
+            break;
+        }
+        tokens.next();
+        match option {
+            "-bm" => multiplier = parser.float(tokens.next(), "bump multiplier")?,
+            // Options with up to three numbers.
+            "-o" | "-s" | "-t" | "-mm" => {
+                for _ in 0..3 {
+                    match tokens.peek() {
+                        Some(t) if t.parse::<f32>().is_ok() => tokens.next(),
+                        _ => break,
+                    };
+                }
+            }
+            _ => {
+                tokens.next();
+            }
+        }
+    }
+
+    let file = tokens.collect::<Vec<_>>().join(" ");
+    if file.is_empty() {
+        return Err(parser.error("texture map without a file"));
+    }
+    Ok((file, multiplier))
+}
+
+/// Loads the image of a texture map as linear data, for normal and height
+/// maps, or the alpha channel for opacity maps.
+fn load_data_map(
+    parser: &Parser,
+    path: &Path,
+    channel: ImageChannel,
+) -> Result<Arc<dyn Texture>, ObjError> {
+    let image = match channel {
+        ImageChannel::Color => load_image(path, ColorSpace::Linear),
+        ImageChannel::Alpha => load_image_alpha(path),
+    };
+    let image = image.map_err(|e| {
+        parser.error(format!(
+            "failed to load texture '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+    Ok(Arc::new(ImageTexture::new(
+        Arc::new(image),
+        WrapMode::Repeat,
+        Filter::Bilinear,
+    )))
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|e| ObjError {
+        file: path.to_path_buf(),
+        line: 0,
+        message: e.to_string(),
+    })
+}
+
+/// Strips comments and splits a line into its keyword and arguments.
+fn tokenize(line: &str) -> Option<(&str, &str)> {
+    let line = match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+    .trim();
+    if line.is_empty() {
+        return None;
+    }
+    match line.find(char::is_whitespace) {
+        Some(i) => Some((&line[..i], line[i..].trim_start())),
+        None => Some((line, "")),
+    }
+}
+
+pub fn load_mtl(path: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut parser = Parser {
+        file: path,
+        line: 0,
+    };
+    let mut materials: Vec<MtlMaterial> = vec![];
+
+    for (line_index, line) in source.lines().enumerate() {
+        parser.line = line_index + 1;
+        let (keyword, rest) = match tokenize(line) {
+            Some(t) => t,
+            None => continue,
+        };
+        let mut tokens = rest.split_whitespace();
+
+        if keyword == "newmtl" {
+            if rest.is_empty() {
+                return Err(parser.error("newmtl without a name"));
+            }
+            materials.push(MtlMaterial::new(rest));
+            continue;
+        }
+
+        let current = match materials.last_mut() {
+            Some(m) => m,
+            None => {
+                return Err(parser.error(format!("'{}' before any newmtl", keyword)));
+            }
+        };
+
+        match keyword {
+            "Kd" => current.kd = parser.color(&mut tokens)?,
+            "Ks" => current.ks = parser.color(&mut tokens)?,
+            "Ke" => current.ke = parser.color(&mut tokens)?,
+            "Tf" => current.tf = parser.color(&mut tokens)?,
+            "Ns" => current.ns = parser.float(tokens.next(), "Ns")?,
+            "Ni" => current.ni = parser.float(tokens.next(), "Ni")?,
+            "d" => current.d = parser.float(tokens.next(), "d")?,
+            "Tr" => current.d = 1.0 - parser.float(tokens.next(), "Tr")?,
+            "Pr" => current.pr = Some(parser.float(tokens.next(), "Pr")?),
+            "Pm" => current.pm = Some(parser.float(tokens.next(), "Pm")?),
+            "Ps" => current.ps = parser.float(tokens.next(), "Ps")?,
+            "Pc" => current.pc = parser.float(tokens.next(), "Pc")?,
+            "Pcr" => current.pcr = parser.float(tokens.next(), "Pcr")?,
+            "aniso" => current.aniso = parser.float(tokens.next(), "aniso")?,
+            "illum" => {
+                let token = tokens.next().unwrap_or("");
+                current.illum = token
+                    .parse()
+                    .map_err(|_| parser.error(format!("invalid illum '{}'", token)))?;
+            }
+            "norm" => {
+                let (file, _) = map_statement(&parser, rest)?;
+                let path = dir.join(file);
+                current.normal_map = Some(load_data_map(&parser, &path, ImageChannel::Color)?);
+            }
+            "bump" | "map_Bump" | "map_bump" => {
+                let (file, multiplier) = map_statement(&parser, rest)?;
+                let path = dir.join(file);
+                current.bump = Some((
+                    load_data_map(&parser, &path, ImageChannel::Color)?,
+                    multiplier,
+                ));
+            }
+            "map_d" => {
+                let (file, _) = map_statement(&parser, rest)?;
+                let path = dir.join(file);
+                current.alpha = Some(load_data_map(&parser, &path, ImageChannel::Alpha)?);
+            }
+            // Everything else (Ka, color texture maps, ...) is not supported yet.
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Loads an OBJ file into triangle meshes, one per group and material.
+/// Polygons are triangulated, `mtllib` files are resolved relative to the
+/// OBJ file and faces without a material use `default_material`. Materials
+/// from the MTL files are added to `table`.
+pub fn load_obj(
+    path: &Path,
+    default_material: MaterialId,
+    table: &mut MaterialTable,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut parser = Parser {
+        file: path,
+        line: 0,
+    };
+
+    let mut positions: Vec<Vec3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+
+    let mut materials: HashMap<String, MaterialId> = HashMap::new();
+    let mut current_material = default_material;
+
+    let mut builders: Vec<MeshBuilder> = vec![];
+    let mut current: Option<usize> = None;
+    let mut polygon: Vec<FaceVertex> = vec![];
+
+    for (line_index, line) in source.lines().enumerate() {
+        parser.line = line_index + 1;
+        let (keyword, rest) = match tokenize(line) {
+            Some(t) => t,
+            None => continue,
+        };
+        let mut tokens = rest.split_whitespace();
+
+        match keyword {
+            "v" => positions.push(parser.vec3(&mut tokens)?),
+            "vn" => normals.push(parser.vec3(&mut tokens)?),
+            "vt" => {
+                let u = parser.float(tokens.next(), "u coordinate")?;
+                let v = match tokens.next() {
+                    Some(t) => parser.float(Some(t), "v coordinate")?,
+                    None => 0.0,
+                };
+                uvs.push([u, v]);
+            }
+            "f" => {
+                polygon.clear();
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let p = parts.next().unwrap_or("");
+                    let uv = parts.next().filter(|s| !s.is_empty());
+                    let n = parts.next().filter(|s| !s.is_empty());
+                    polygon.push(FaceVertex {
+                        p: parser.index(p, positions.len(), "vertex")?,
+                        uv: match uv {
+                            Some(t) => Some(parser.index(t, uvs.len(), "texture coordinate")?),
+                            None => None,
+                        },
+                        n: match n {
+                            Some(t) => Some(parser.index(t, normals.len(), "normal")?),
+                            None => None,
+                        },
+                    });
+                }
+                if polygon.len() < 3 {
+                    return Err(parser.error("face needs at least 3 vertices"));
+                }
+
+                let builder = match current {
+                    Some(i) => i,
+                    None => {
+                        builders.push(MeshBuilder {
+                            material: current_material,
+                            faces: vec![],
+                        });
+                        builders.len() - 1
+                    }
+                };
+                current = Some(builder);
+
+                for [a, b, c] in triangulate(&positions, &polygon) {
+                    builders[builder]
+                        .faces
+                        .push([polygon[a], polygon[b], polygon[c]]);
+                }
+            }
+            "g" | "o" => current = None,
+            "usemtl" => {
+                // Materials missing from the MTL files fall back to the default
+                // rather than failing, as many exported assets have them.
+                current_material = match materials.get(rest) {
+                    Some(m) => *m,
+                    None => default_material,
+                };
+                current = None;
+            }
+            "mtllib" => {
+                for name in tokens {
+                    for m in load_mtl(&dir.join(name))? {
+                        let id = table.add(m.to_material());
+                        if let Some(alpha) = m.alpha.as_ref().filter(|_| !m.is_emissive()) {
+                            let mask = AlphaMask::new(Arc::clone(alpha), AlphaMode::Threshold, 0.5);
+                            table.set_alpha(id, mask).map_err(|e| parser.error(e))?;
+                        }
+                        materials.insert(m.name.clone(), id);
+                    }
+                }
+            }
+            // Smoothing groups, lines, points, free-form geometry, ...
+            _ => {}
+        }
+    }
+
+    Ok(builders
+        .into_iter()
+        .filter(|b| !b.faces.is_empty())
+        .map(|b| build_mesh(&positions, &normals, &uvs, b))
+        .collect())
+}
+
+/// Copies the vertices referenced by the builder's faces into a mesh of its
+/// own, so meshes don't keep the whole file's buffers alive.
+fn build_mesh(
+    positions: &[Vec3],
+    normals: &[Vec3],
+    uvs: &[[f32; 2]],
+    builder: MeshBuilder,
+) -> TriangleMesh {
+    fn remap<T: Copy>(src: &[T], dst: &mut Vec<T>, map: &mut HashMap<u32, u32>, i: u32) -> u32 {
+        *map.entry(i).or_insert_with(|| {
+            dst.push(src[i as usize]);
+            dst.len() as u32 - 1
+        })
+    }
+
+    let has_normals = builder.faces.iter().flatten().all(|v| v.n.is_some());
+    let has_uvs = builder.faces.iter().flatten().all(|v| v.uv.is_some());
+
+    let mut mesh_positions = vec![];
+    let mut mesh_normals = vec![];
+    let mut mesh_uvs = vec![];
+    let mut position_map = HashMap::new();
+    let mut normal_map = HashMap::new();
+    let mut uv_map = HashMap::new();
+
+    let mut indices = Vec::with_capacity(builder.faces.len());
+    let mut normal_indices = vec![];
+    let mut uv_indices = vec![];
+
+    for face in &builder.faces {
+        indices.push(face.map(|v| remap(positions, &mut mesh_positions, &mut position_map, v.p)));
+        if has_normals {
+            normal_indices.push(
+                face.map(|v| remap(normals, &mut mesh_normals, &mut normal_map, v.n.unwrap())),
+            );
+        }
+        if has_uvs {
+            uv_indices.push(face.map(|v| remap(uvs, &mut mesh_uvs, &mut uv_map, v.uv.unwrap())));
+        }
+    }
+
+    let mut mesh = TriangleMesh::new(mesh_positions, indices, builder.material);
+    if has_normals {
+        mesh = mesh.with_normals(mesh_normals, normal_indices);
+    }
+    if has_uvs {
+        mesh = mesh.with_uvs(mesh_uvs, uv_indices);
+    }
+    mesh
+}
+
+/// Splits a polygon into triangles by ear clipping in the plane of the
+/// polygon. Returns indices into `polygon`.
+fn triangulate(positions: &[Vec3], polygon: &[FaceVertex]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let points: Vec<Vec3> = polygon.iter().map(|v| positions[v.p as usize]).collect();
+
+    // Newell's method gives a robust normal even for concave polygons.
+    let mut normal = Vec3::ZERO;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        normal += vec3!(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y)
+        );
+    }
+
+    let fan = || -> Vec<[usize; 3]> { (1..n - 1).map(|i| [0, i, i + 1]).collect() };
+    if normal.near_zero() {
+        return fan();
+    }
+
+    let is_convex = |a: usize, b: usize, c: usize| {
+        (points[b] - points[a])
+            .cross(points[c] - points[b])
+            .dot(normal)
+            > 0.0
+    };
+    let inside = |p: Vec3, a: usize, b: usize, c: usize| {
+        let (a, b, c) = (points[a], points[b], points[c]);
+        (b - a).cross(p - a).dot(normal) >= 0.0
+            && (c - b).cross(p - b).dot(normal) >= 0.0
+            && (a - c).cross(p - c).dot(normal) >= 0.0
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let a = remaining[(i + m - 1) % m];
+            let b = remaining[i];
+            let c = remaining[(i + 1) % m];
+            is_convex(a, b, c)
+                && remaining
+                    .iter()
+                    .filter(|&&j| j != a && j != b && j != c)
+                    .all(|&j| !inside(points[j], a, b, c))
+        });
+
+        // Self-intersecting or otherwise broken polygons have no ear left.
+        let ear = match ear {
+            Some(i) => i,
+            None => return fan(),
+        };
+
+        triangles.push([
+            remaining[(ear + m - 1) % m],
+            remaining[ear],
+            remaining[(ear + 1) % m],
+        ]);
+        remaining.remove(ear);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    fn parser() -> Parser<'static> {
+        Parser {
+            file: Path::new("test.obj"),
+            line: 7,
+        }
+    }
+
+    fn vertex(p: u32) -> FaceVertex {
+        FaceVertex {
+            p,
+            uv: None,
+            n: None,
+        }
+    }
+
+    fn area(positions: &[Vec3], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                0.5 * (positions[b] - positions[a])
+                    .cross(positions[c] - positions[a])
+                    .len()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn index_resolves_positive_and_negative() {
+        let p = parser();
+        assert_eq!(p.index("1", 4, "vertex").unwrap(), 0);
+        assert_eq!(p.index("4", 4, "vertex").unwrap(), 3);
+        assert_eq!(p.index("-1", 4, "vertex").unwrap(), 3);
+        assert_eq!(p.index("-4", 4, "vertex").unwrap(), 0);
+    }
+
+    #[test]
+    fn index_rejects_out_of_range() {
+        let p = parser();
+        for token in ["0", "5", "-5"] {
+            let e = p.index(token, 4, "vertex").unwrap_err();
+            assert_eq!(e.line, 7);
+            assert!(e.message.contains("out of range"), "{}", e.message);
+        }
+        let e = p.index("x", 4, "normal").unwrap_err();
+        assert_eq!(e.message, "invalid normal index 'x'");
+        assert_eq!(e.to_string(), "test.obj:7: invalid normal index 'x'");
+    }
+
+    #[test]
+    fn tokenize_strips_comments() {
+        assert_eq!(tokenize("  v 1 2 3 # comment"), Some(("v", "1 2 3")));
+        assert_eq!(tokenize("newmtl"), Some(("newmtl", "")));
+        assert_eq!(tokenize("# only a comment"), None);
+        assert_eq!(tokenize("   "), None);
+    }
+
+    #[test]
+    fn map_statement_reads_options() {
+        let p = parser();
+        assert_eq!(
+            map_statement(&p, "-bm 0.5 bumps.png").unwrap(),
+            ("bumps.png".to_string(), 0.5)
+        );
+        assert_eq!(
+            map_statement(&p, "-o 0.1 0.2 -s 2 -clamp on my map.png").unwrap(),
+            ("my map.png".to_string(), 1.0)
+        );
+        assert!(map_statement(&p, "-bm 2").is_err());
+    }
+
+    #[test]
+    fn triangulate_convex_polygon() {
+        let positions = [
+            vec3!(0, 0, 0),
+            vec3!(1, 0, 0),
+            vec3!(1, 1, 0),
+            vec3!(0, 1, 0),
+        ];
+        let polygon: Vec<FaceVertex> = (0..4).map(vertex).collect();
+        let triangles = triangulate(&positions, &polygon);
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&positions, &triangles) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn triangulate_concave_polygon() {
+        // A U shape: a fan from the first vertex would fold over the notch.
+        let positions = [
+            vec3!(0, 0, 0),
+            vec3!(3, 0, 0),
+            vec3!(3, 2, 0),
+            vec3!(2, 2, 0),
+            vec3!(2, 1, 0),
+            vec3!(1, 1, 0),
+            vec3!(1, 2, 0),
+            vec3!(0, 2, 0),
+        ];
+        let polygon: Vec<FaceVertex> = (0..8).map(vertex).collect();
+        let triangles = triangulate(&positions, &polygon);
+        assert_eq!(triangles.len(), 6);
+        assert!((area(&positions, &triangles) - 5.0).abs() < 1e-6);
+
+        // Every triangle keeps the polygon's winding.
+        for &[a, b, c] in &triangles {
+            let n = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+            assert!(n.z > 0.0);
+        }
+    }
+
+    #[test]
+    fn load_mtl_reads_keywords() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "keywords.mtl",
+            "# materials\n\
+             newmtl red\n\
+             Kd 0.8 0.1 0.1\n\
+             Ks 0.5\n\
+             Ns 250\n\
+             Tr 0.25\n\
+             illum 4\n\
+             newmtl pbr\n\
+             Pr 0.3\n\
+             Pm 1\n\
+             Pc 0.5\n\
+             Pcr 0.2\n\
+             aniso 0.4\n\
+             Ke 2 2 2\n",
+        );
+        let materials = load_mtl(&path).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let red = &materials[0];
+        assert_eq!(red.name, "red");
+        assert!(red.kd == color!(0.8, 0.1, 0.1));
+        assert!(red.ks == color!(0.5));
+        assert_eq!(red.ns, 250.0);
+        assert_eq!(red.d, 0.75);
+        assert_eq!(red.illum, 4);
+        assert_eq!(red.pr, None);
+
+        let pbr = &materials[1];
+        assert_eq!(pbr.pr, Some(0.3));
+        assert_eq!(pbr.pm, Some(1.0));
+        assert_eq!(pbr.pc, 0.5);
+        assert_eq!(pbr.pcr, 0.2);
+        assert_eq!(pbr.aniso, 0.4);
+        assert!(pbr.ke == color!(2.0));
+    }
+
+    #[test]
+    fn ior_falls_back_for_unset_ni() {
+        let mut material = MtlMaterial::new("glass");
+        for (ni, ior) in [
+            (0.0, 1.5),
+            (-2.0, 1.5),
+            (0.5, 1.0),
+            (1.33, 1.33),
+            (10.0, 3.0),
+        ] {
+            material.ni = ni;
+            assert_eq!(material.ior(), ior);
+        }
+    }
+
+    #[test]
+    fn load_mtl_reports_line() {
+        let dir = TempDir::new();
+        let path = dir.write("orphan.mtl", "\nKd 1 1 1\n");
+        let e = load_mtl(&path).err().unwrap();
+        assert_eq!(e.line, 2);
+        assert_eq!(e.message, "'Kd' before any newmtl");
+
+        let path = dir.write("bad.mtl", "newmtl a\nNs shiny\n");
+        let e = load_mtl(&path).err().unwrap();
+        assert_eq!(e.line, 2);
+        assert_eq!(e.message, "invalid Ns 'shiny'");
+    }
+
+    #[test]
+    fn load_obj_resolves_relative_indices() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "quad.obj",
+            "v 0 0 0\n\
+             v 1 0 0\n\
+             v 1 1 0\n\
+             v 0 1 0\n\
+             vt 0 0\n\
+             vt 1 0\n\
+             vt 1 1\n\
+             vt 0 1\n\
+             f -4/-4 -3/-3 -2/-2 -1/-1\n",
+        );
+        let mut table = MaterialTable::new();
+        let meshes = load_obj(&path, 0, &mut table).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.indices.len(), 2);
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.uvs.len(), 4);
+        assert_eq!(mesh.indices, mesh.uv_indices);
+    }
+
+    #[test]
+    fn load_obj_rejects_out_of_range_index() {
+        let dir = TempDir::new();
+        let path = dir.write("broken.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n");
+        let mut table = MaterialTable::new();
+        let e = load_obj(&path, 0, &mut table).err().unwrap();
+        assert_eq!(e.line, 4);
+        assert_eq!(e.message, "vertex index 4 out of range (have 3)");
+    }
+
+    #[test]
+    fn load_obj_keeps_emitters_unmasked() {
+        let dir = TempDir::new();
+        let mask = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/leaf.png");
+        dir.write(
+            "masked.mtl",
+            format!(
+                "newmtl lamp\nKe 1 1 1\nmap_d {0}\nnewmtl leaf\nKd 0.2 0.5 0.1\nmap_d {0}\n",
+                mask.display()
+            ),
+        );
+        let path = dir.write("masked.obj", "mtllib masked.mtl\n");
+        let mut table = MaterialTable::new();
+        load_obj(&path, 0, &mut table).unwrap();
+        assert!(table.alpha(0).is_none());
+        assert!(table.alpha(1).is_some());
+    }
+}
@@ -0,0 +1,426 @@
+use crate::tonemap::*;
+use crate::vec3::*;
+use crate::RenderBuffer;
+use bmp::{Image, Pixel};
+use exr::meta::attribute::{AttributeValue, Text};
+use exr::prelude::{Encoding, Layer, LayerAttributes, SpecificChannels, WritableImage};
+use half::f16;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Bmp,
+    Png,
+    Png16,
+    /// OpenEXR with half float channels.
+    Exr,
+    /// OpenEXR with full float channels.
+    Exr32,
+    /// Radiance RGBE.
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "bmp" => Some(OutputFormat::Bmp),
+            "png" => Some(OutputFormat::Png),
+            "png16" => Some(OutputFormat::Png16),
+            "exr" => Some(OutputFormat::Exr),
+            "exr32" => Some(OutputFormat::Exr32),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+}
+
+/// Render settings recorded alongside the image, in formats that can store
+/// them.
+pub struct ImageMetadata {
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    pub seed: u32,
+    pub render_time: Duration,
+}
+
+impl ImageMetadata {
+    fn entries(&self) -> [(&'static str, String); 5] {
+        [
+            ("Software", String::from("rust-raytracer")),
+            ("Samples per pixel", self.samples_per_pixel.to_string()),
+            ("Bounces", self.max_bounces.to_string()),
+            ("Seed", self.seed.to_string()),
+            (
+                "Render time",
+                format!("{:.3}s", self.render_time.as_secs_f64()),
+            ),
+        ]
+    }
+}
+
+/// sRGB transfer function (IEC 61966-2-1), clamping to `[0, 1]`.
+#[inline(always)]
+pub fn linear_to_srgb(x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline(always)]
+fn encode_srgb(color: Color, tone_mapping: &ToneMapping) -> [f32; 3] {
+    let color = tone_mapping.apply(color);
+    [
+        linear_to_srgb(color.x),
+        linear_to_srgb(color.y),
+        linear_to_srgb(color.z),
+    ]
+}
+
+fn write_png(
+    render_buffer: &RenderBuffer,
+    path: &Path,
+    bit_depth: png::BitDepth,
+    tone_mapping: &ToneMapping,
+    metadata: &ImageMetadata,
+) -> io::Result<()> {
+    let mut data = Vec::new();
+    for y in 0..render_buffer.h {
+        for x in 0..render_buffer.w {
+            for c in encode_srgb(render_buffer.get_pixel_color(x, y), tone_mapping) {
+                match bit_depth {
+                    png::BitDepth::Sixteen => {
+                        let value = (c * 65535.0 + 0.5) as u16;
+                        data.extend_from_slice(&value.to_be_bytes());
+                    }
+                    _ => data.push((c * 255.0 + 0.5) as u8),
+                }
+            }
+        }
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, render_buffer.w, render_buffer.h);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    for (keyword, text) in metadata.entries() {
+        encoder.add_text_chunk(keyword.to_string(), text)?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_exr(
+    render_buffer: &RenderBuffer,
+    path: &Path,
+    half: bool,
+    metadata: &ImageMetadata,
+) -> io::Result<()> {
+    let mut attributes = LayerAttributes::named("rgb");
+    for (keyword, text) in metadata.entries() {
+        attributes.other.insert(
+            Text::from(keyword),
+            AttributeValue::Text(Text::from(&text[..])),
+        );
+    }
+
+    let size = (render_buffer.w as usize, render_buffer.h as usize);
+    let pixel = |x: usize, y: usize| render_buffer.get_pixel_color(x as u32, y as u32);
+    let result = if half {
+        let channels = SpecificChannels::rgb(|p: exr::math::Vec2<usize>| {
+            let c = pixel(p.x(), p.y());
+            (f16::from_f32(c.x), f16::from_f32(c.y), f16::from_f32(c.z))
+        });
+        exr::image::Image::from_layer(Layer::new(
+            size,
+            attributes,
+            Encoding::SMALL_LOSSLESS,
+            channels,
+        ))
+        .write()
+        .to_file(path)
+    } else {
+        let channels = SpecificChannels::rgb(|p: exr::math::Vec2<usize>| {
+            let c = pixel(p.x(), p.y());
+            (c.x, c.y, c.z)
+        });
+        exr::image::Image::from_layer(Layer::new(
+            size,
+            attributes,
+            Encoding::SMALL_LOSSLESS,
+            channels,
+        ))
+        .write()
+        .to_file(path)
+    };
+
+    result.map_err(|e| match e {
+        exr::error::Error::Io(e) => e,
+        e => io::Error::other(e),
+    })
+}
+
+/// Shared-exponent encoding used by Radiance files: three 8-bit mantissas
+/// and a biased exponent.
+#[inline(always)]
+fn color_to_rgbe(color: Color) -> [u8; 4] {
+    let r = color.x.max(0.0);
+    let g = color.y.max(0.0);
+    let b = color.z.max(0.0);
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0; 4];
+    }
+    let v = v.min(f32::MAX);
+
+    // v = m * 2^e with m in [0.5, 1).
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f32.powi(e) >= 1.0 {
+        e += 1;
+    } else if v / 2f32.powi(e) < 0.5 {
+        e -= 1;
+    }
+    let scale = 256.0 / 2f32.powi(e);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+/// Run-length encodes one component of a scanline: runs of at least three
+/// equal bytes become `128 + count, value`, everything else is stored as
+/// `count, bytes...`.
+fn write_rle_component(out: &mut Vec<u8>, data: &[u8]) {
+    const MIN_RUN: usize = 3;
+    let mut i = 0;
+    while i < data.len() {
+        // Find the next run long enough to be worth encoding.
+        let mut run_start = i;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = 1;
+            while run_start + run_len < data.len()
+                && run_len < 127
+                && data[run_start + run_len] == data[run_start]
+            {
+                run_len += 1;
+            }
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += run_len;
+        }
+
+        while i < run_start {
+            let count = usize::min(run_start - i, 128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[i..i + count]);
+            i += count;
+        }
+
+        if run_len >= MIN_RUN {
+            out.push(128 + run_len as u8);
+            out.push(data[run_start]);
+            i = run_start + run_len;
+        }
+    }
+}
+
+fn write_hdr(render_buffer: &RenderBuffer, path: &Path) -> io::Result<()> {
+    let (w, h) = (render_buffer.w, render_buffer.h);
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        h, w
+    )?;
+
+    let mut scanline = vec![[0u8; 4]; w as usize];
+    let mut component = vec![0u8; w as usize];
+    let mut encoded = Vec::new();
+    for y in 0..h {
+        for (x, rgbe) in scanline.iter_mut().enumerate() {
+            *rgbe = color_to_rgbe(render_buffer.get_pixel_color(x as u32, y));
+        }
+
+        // Scanlines outside of this range can only be stored flat.
+        if !(8..0x8000).contains(&w) {
+            out.write_all(scanline.as_flattened())?;
+            continue;
+        }
+
+        encoded.clear();
+        encoded.extend_from_slice(&[2, 2, (w >> 8) as u8, w as u8]);
+        for c in 0..4 {
+            for (dst, rgbe) in component.iter_mut().zip(&scanline) {
+                *dst = rgbe[c];
+            }
+            write_rle_component(&mut encoded, &component);
+        }
+        out.write_all(&encoded)?;
+    }
+
+    out.flush()
+}
+
+/// Little-endian PFM, stored bottom row first.
+fn write_pfm(render_buffer: &RenderBuffer, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "PF\n{} {}\n-1.0\n", render_buffer.w, render_buffer.h)?;
+    for y in (0..render_buffer.h).rev() {
+        for x in 0..render_buffer.w {
+            let c = render_buffer.get_pixel_color(x, y);
+            for value in [c.x, c.y, c.z] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+/// Writes the render buffer, which holds linear radiance. Low dynamic range
+/// formats are tone mapped and sRGB encoded, the floating point ones store
+/// the radiance as is.
+pub fn write_image(
+    render_buffer: &RenderBuffer,
+    path: &Path,
+    format: OutputFormat,
+    tone_mapping: &ToneMapping,
+    metadata: &ImageMetadata,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Bmp => {
+            let mut image = Image::new(render_buffer.w, render_buffer.h);
+            for (x, y) in image.coordinates() {
+                let [r, g, b] = encode_srgb(render_buffer.get_pixel_color(x, y), tone_mapping);
+                image.set_pixel(
+                    x,
+                    y,
+                    px!(
+                        (r * 255.0 + 0.5) as u32,
+                        (g * 255.0 + 0.5) as u32,
+                        (b * 255.0 + 0.5) as u32
+                    ),
+                );
+            }
+            image.save(path)
+        }
+        OutputFormat::Png => write_png(
+            render_buffer,
+            path,
+            png::BitDepth::Eight,
+            tone_mapping,
+            metadata,
+        ),
+        OutputFormat::Png16 => write_png(
+            render_buffer,
+            path,
+            png::BitDepth::Sixteen,
+            tone_mapping,
+            metadata,
+        ),
+        OutputFormat::Exr => write_exr(render_buffer, path, true, metadata),
+        OutputFormat::Exr32 => write_exr(render_buffer, path, false, metadata),
+        OutputFormat::Hdr => write_hdr(render_buffer, path),
+        OutputFormat::Pfm => write_pfm(render_buffer, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::load_hdr;
+    use crate::test_util::*;
+
+    #[test]
+    fn rgbe_encoding() {
+        assert_eq!(color_to_rgbe(vec3!(1.0, 1.0, 1.0)), [128, 128, 128, 129]);
+        assert_eq!(color_to_rgbe(vec3!(0.5, 0.25, 0.0)), [128, 64, 0, 128]);
+        // Black, negative and vanishingly small colors share the zero code.
+        assert_eq!(color_to_rgbe(Color::ZERO), [0; 4]);
+        assert_eq!(color_to_rgbe(vec3!(-1.0, -2.0, 0.0)), [0; 4]);
+        assert_eq!(color_to_rgbe(vec3!(1e-33, 0.0, 0.0)), [0; 4]);
+    }
+
+    #[test]
+    fn rle_component_encoding() {
+        let mut out = Vec::new();
+        write_rle_component(&mut out, &[1, 2, 7, 7, 7, 7, 3]);
+        assert_eq!(out, [2, 1, 2, 128 + 4, 7, 1, 3]);
+
+        // Runs are capped at 127 and literals at 128 bytes.
+        out.clear();
+        write_rle_component(&mut out, &[5; 130]);
+        assert_eq!(out, [128 + 127, 5, 128 + 3, 5]);
+        out.clear();
+        let literal: Vec<u8> = (0..130).map(|i| i as u8).collect();
+        write_rle_component(&mut out, &literal);
+        assert_eq!(out.len(), 2 + 130);
+        assert_eq!(out[0], 128);
+        assert_eq!(out[129], 2);
+    }
+
+    /// Writes an image through `write_hdr` and reads it back, for a width
+    /// stored flat and one stored run-length encoded.
+    #[test]
+    fn hdr_round_trip() {
+        let dir = TempDir::new();
+
+        for (w, h) in [(5, 3), (40, 4)] {
+            let mut render_buffer = RenderBuffer::new(w, h);
+            for (i, pixel) in render_buffer.pixels.iter_mut().enumerate() {
+                // Long runs of equal pixels mixed with varied ones, over a
+                // wide dynamic range.
+                *pixel = if i % 10 < 6 {
+                    vec3!(0.25, 0.5, 1.0)
+                } else {
+                    let v = 1.7f32.powi(i as i32 % 40 - 20);
+                    vec3!(v, 0.3 * v, 0.01 * v)
+                };
+            }
+
+            let path = dir.path(&format!("round-trip-{}.hdr", w));
+            write_hdr(&render_buffer, &path).unwrap();
+            let image = load_hdr(&path).unwrap();
+            assert_eq!((image.width, image.height), (w, h));
+
+            for y in 0..h {
+                for x in 0..w {
+                    let expected = render_buffer.get_pixel_color(x, y);
+                    let actual = image.get(x, y);
+                    // Mantissas have 8 bits relative to the largest channel.
+                    let tolerance = expected.x.max(expected.y).max(expected.z) / 128.0;
+                    for axis in 0..3 {
+                        assert!(
+                            (actual[axis] - expected[axis]).abs() <= tolerance,
+                            "pixel ({}, {}) of {}x{}: {} != {}",
+                            x,
+                            y,
+                            w,
+                            h,
+                            actual[axis],
+                            expected[axis]
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::light::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+
+/// Scene units per kcd/m² of sky luminance (and per klx of sun
+/// illuminance), chosen so a clear day needs no exposure adjustment.
+const SCALE: f32 = 0.05;
+
+/// Illuminance of the sun above the atmosphere, in klx.
+const SUN_ILLUMINANCE: f32 = 127.0;
+
+/// Clear sky radiance after Preetham, Shirley and Smits, "A Practical
+/// Analytic Model for Daylight" (1999), with the sun as a separate light.
+#[derive(Clone, Copy)]
+pub struct PhysicalSky {
+    /// Unit vector towards the sun.
+    pub sun_direction: Vec3,
+    pub turbidity: f32,
+    pub intensity: f32,
+    /// Angular diameter of the sun disk, in degrees.
+    pub sun_size: f32,
+    /// Zenith luminance and chromaticity: Y, x, y.
+    zenith: [f32; 3],
+    /// Perez distribution coefficients A to E for Y, x and y.
+    perez: [[f32; 5]; 3],
+}
+
+/// Perez et al. all-weather luminance distribution.
+#[inline(always)]
+fn perez_distribution(c: &[f32; 5], cos_theta: f32, gamma: f32, cos_gamma: f32) -> f32 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+impl PhysicalSky {
+    /// `elevation` is the sun's angle above the horizon and `azimuth` its
+    /// angle from -z towards +x, both in degrees. Turbidity ranges from 2
+    /// (very clear) to about 10 (hazy).
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32, intensity: f32) -> Self {
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let sun_direction = vec3!(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos()
+        );
+
+        let t = turbidity;
+        let theta_s = 0.5 * PI - elevation;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Normalize so that the zenith gets exactly the zenith values.
+        let cos_theta_s = theta_s.cos();
+        let mut zenith = [zenith_luminance, zenith_x, zenith_y];
+        for (z, c) in zenith.iter_mut().zip(&perez) {
+            *z /= perez_distribution(c, 1.0, theta_s, cos_theta_s);
+        }
+
+        Self {
+            sun_direction,
+            turbidity,
+            intensity,
+            sun_size: 0.53,
+            zenith,
+            perez,
+        }
+    }
+
+    /// Sky radiance along `r`, without the sun. Directions below the horizon
+    /// get the horizon's color.
+    #[inline(always)]
+    pub fn color(&self, r: Ray) -> Color {
+        let d = r.direction.normalized();
+        let cos_theta = d.y.max(0.01);
+        let cos_gamma = d.dot(self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+
+        let [big_y, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez_distribution(&self.perez[i], cos_theta, gamma, cos_gamma)
+        });
+        if y <= 0.0 {
+            return Color::ZERO;
+        }
+
+        // xyY to XYZ to linear sRGB.
+        let big_x = x / y * big_y;
+        let big_z = (1.0 - x - y) / y * big_y;
+        let rgb = vec3!(
+            3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z
+        );
+        Vec3::max(rgb, Color::ZERO) * (SCALE * self.intensity)
+    }
+
+    /// Fraction of sunlight reaching the ground at red, green and blue
+    /// wavelengths, from Rayleigh and aerosol scattering (Preetham et al.,
+    /// appendix A.2).
+    fn sun_transmittance(&self) -> Color {
+        let theta_s = self.sun_direction.y.clamp(0.0, 1.0).acos();
+        let theta_deg = theta_s.to_degrees();
+        // Relative optical mass, which grows towards the horizon.
+        let m = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_deg).max(1e-3).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let alpha = 1.3;
+
+        let transmittance = |lambda_um: f32| {
+            let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * m).exp();
+            let aerosol = (-beta * lambda_um.powf(-alpha) * m).exp();
+            rayleigh * aerosol
+        };
+        vec3!(
+            transmittance(0.65),
+            transmittance(0.55),
+            transmittance(0.45)
+        )
+    }
+
+    /// The sun as a directional light matching the sky.
+    pub fn sun_light(&self) -> Light {
+        Light::Directional {
+            direction: self.sun_direction,
+            irradiance: self.sun_transmittance() * (SUN_ILLUMINANCE * SCALE * self.intensity),
+            cos_angular_radius: (0.5 * self.sun_size.to_radians()).cos(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::*;
+
+    #[test]
+    fn sky_is_finite_and_non_negative() {
+        let mut rng = Rng::new(13);
+        for elevation in [0.0, 5.0, 30.0, 60.0, 90.0] {
+            for turbidity in [2.0, 5.0, 10.0] {
+                let sky = PhysicalSky::new(elevation, 40.0, turbidity, 1.0);
+                let mut directions = vec![vec3!(0, 1, 0), vec3!(1, 0, 0), sky.sun_direction];
+                for _ in 0..200 {
+                    let d = Vec3::random_unit_vector(&mut rng);
+                    directions.push(vec3!(d.x, d.y.abs(), d.z));
+                }
+
+                for d in directions {
+                    let c = sky.color(Ray::new(Vec3::ZERO, d));
+                    for i in 0..3 {
+                        assert!(c[i].is_finite() && c[i] >= 0.0);
+                    }
+                }
+            }
+        }
+    }
+}
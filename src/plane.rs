@@ -0,0 +1,107 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+/// Infinite plane through `point`, facing `normal` (normalized). UVs are
+/// distances along two fixed tangents, so textures tile in world units.
+/// Having no bounds, planes are kept out of the BVH and tested on their
+/// own; as emitters they can only be found by BSDF sampling.
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: MaterialId,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: MaterialId) -> Self {
+        Self {
+            point,
+            normal: normal.normalized(),
+            material,
+        }
+    }
+
+    /// Like `hit`, with the surface cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let denom = self.normal.dot(r.direction);
+        if denom == 0.0 {
+            return false;
+        }
+        let t = self.normal.dot(self.point - r.origin) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        record_hit(alpha, r, rec, |rec| {
+            let (s, t_) = self.normal.orthonormal_basis();
+            rec.t = t;
+            rec.p = r.at(t);
+            rec.set_face_normal(r, self.normal);
+            let d = rec.p - self.point;
+            rec.u = d.dot(s);
+            rec.v = d.dot(t_);
+            rec.dpdu = s;
+            rec.dpdv = t_;
+            rec.material = self.material;
+            rec.primitive = 0;
+        })
+    }
+}
+
+impl Hittable for Plane {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(vec3!(-f32::MAX), vec3!(f32::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_from_either_side() {
+        let plane = Plane::new(vec3!(0, -1, 0), vec3!(0, 3, 0), 0);
+        let mut rec = HitRecord::new();
+
+        let r = Ray::new(vec3!(100, 4, -50), vec3!(0, -1, 0));
+        assert!(plane.hit(r, 0.001, f32::MAX, &mut rec));
+        assert_eq!(rec.t, 5.0);
+        assert!(rec.front_face && rec.normal == vec3!(0, 1, 0));
+
+        let r = Ray::new(vec3!(0, -3, 0), vec3!(1, 1, 0));
+        assert!(plane.hit(r, 0.001, f32::MAX, &mut rec));
+        assert!(!rec.front_face && rec.normal == vec3!(0, -1, 0));
+
+        // Parallel, and pointing away.
+        assert!(!plane.hit(
+            Ray::new(vec3!(0, 0, 0), vec3!(1, 0, 0)),
+            0.001,
+            f32::MAX,
+            &mut rec
+        ));
+        assert!(!plane.hit(
+            Ray::new(vec3!(0, 0, 0), vec3!(0, 1, 0)),
+            0.001,
+            f32::MAX,
+            &mut rec
+        ));
+    }
+}
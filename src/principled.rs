@@ -0,0 +1,402 @@
+use crate::hittable::*;
+use crate::material::*;
+use crate::microfacet::*;
+use crate::random::*;
+use crate::texture::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Lower bound on roughness. Every lobe is a microfacet lobe that `eval`
+/// must describe, so there is no perfectly smooth case; use `Conductor` or
+/// `Dieletric` for ideal mirrors and glass.
+const MIN_ROUGHNESS: f32 = 0.04;
+
+/// All-in-one material after Burley's "Physically Based Shading at Disney"
+/// (2012) and its 2015 extension to transmission. Every parameter except
+/// `base_color` and `ior` is in [0, 1].
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f32,
+    /// Read through `Texture::scalar`, so any texture can drive it.
+    pub roughness: Arc<dyn Texture>,
+    /// Reflectance of the dielectric base at normal incidence, remapped so
+    /// that 0.5 is 4% (an index of refraction of 1.5).
+    pub specular: f32,
+    /// Tints the dielectric reflection towards the base color.
+    pub specular_tint: f32,
+    /// Stretches the highlight along the surface's first tangent.
+    pub anisotropic: f32,
+    /// Retro-reflective rim for cloth.
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    /// Strength of a second, white specular layer on top.
+    pub clearcoat: f32,
+    /// Smoothness of the clearcoat, 1 being mirror-like.
+    pub clearcoat_gloss: f32,
+    /// Fraction of the dielectric base that refracts instead of diffusing.
+    pub transmission: f32,
+    pub ior: f32,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Arc::new(SolidColor::new(color!(0.8))),
+            metallic: 0.0,
+            roughness: Arc::new(SolidColor::new(color!(0.5))),
+            specular: 0.5,
+            specular_tint: 0.0,
+            anisotropic: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+/// Lobe weights and distributions derived from the parameters, at one
+/// point of the surface.
+struct Lobes {
+    base_color: Color,
+    roughness: f32,
+    metallic: f32,
+    /// Fraction of the dielectric base that refracts.
+    transmission_fraction: f32,
+    /// Dielectric reflectance at normal incidence, tinted.
+    specular_f0: Color,
+    /// Sheen color at grazing angles.
+    sheen: Color,
+    diffuse: f32,
+    transmission: f32,
+    clearcoat: f32,
+    /// Probabilities of sampling the diffuse, specular, clearcoat and
+    /// transmission lobes.
+    pick: [f32; 4],
+    specular_distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+}
+
+#[inline(always)]
+fn luminance(c: Color) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+#[inline(always)]
+fn schlick_weight(cos: f32) -> f32 {
+    let m = (1.0 - cos).clamp(0.0, 1.0);
+    let m2 = m * m;
+    m2 * m2 * m
+}
+
+#[inline(always)]
+fn schlick(f0: Color, cos: f32) -> Color {
+    Vec3::lerp(f0, schlick_weight(cos), color!(1.0))
+}
+
+impl Principled {
+    #[inline(always)]
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        // The parser only accepts [0, 1], but materials can be built in
+        // code too; out of range weights would make lobe probabilities
+        // negative.
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let transmission_fraction = self.transmission.clamp(0.0, 1.0);
+        let diffuse = (1.0 - metallic) * (1.0 - transmission_fraction);
+        let transmission = (1.0 - metallic) * transmission_fraction;
+        let clearcoat = 0.25 * self.clearcoat.clamp(0.0, 1.0);
+
+        // Any positive weights keep the estimator unbiased; these roughly
+        // follow how much energy each lobe reflects.
+        let specular = 0.2 + 0.8 * metallic;
+        let total = diffuse + specular + clearcoat + transmission;
+
+        let roughness = self.roughness.scalar(rec).clamp(MIN_ROUGHNESS, 1.0);
+        let clearcoat_gloss = self.clearcoat_gloss.clamp(0.0, 1.0);
+        let clearcoat_roughness = 0.3 + (MIN_ROUGHNESS - 0.3) * clearcoat_gloss;
+        let base_color = self.base_color.value(rec);
+        let tint = Self::tint(base_color);
+        let specular_tint = Vec3::lerp(color!(1.0), self.specular_tint.clamp(0.0, 1.0), tint);
+        let sheen_tint = Vec3::lerp(color!(1.0), self.sheen_tint.clamp(0.0, 1.0), tint);
+        Lobes {
+            base_color,
+            roughness,
+            metallic,
+            transmission_fraction,
+            specular_f0: specular_tint * (self.specular.clamp(0.0, 1.0) * 0.08),
+            sheen: sheen_tint * self.sheen.clamp(0.0, 1.0),
+            diffuse,
+            transmission,
+            clearcoat,
+            pick: [
+                diffuse / total,
+                specular / total,
+                clearcoat / total,
+                transmission / total,
+            ],
+            specular_distribution: TrowbridgeReitz::anisotropic(
+                roughness,
+                self.anisotropic.clamp(0.0, 1.0),
+            ),
+            clearcoat_distribution: TrowbridgeReitz::from_roughness(clearcoat_roughness),
+        }
+    }
+
+    /// Relative index of refraction across the surface, seen from the side
+    /// `rec.normal` points to.
+    #[inline(always)]
+    fn eta(&self, rec: &HitRecord) -> f32 {
+        // The Fresnel terms turn into NaN for a non-positive index.
+        let ior = if self.ior > 0.0 { self.ior } else { 1.5 };
+        if rec.front_face {
+            ior
+        } else {
+            1.0 / ior
+        }
+    }
+
+    #[inline(always)]
+    fn tint(base_color: Color) -> Color {
+        let l = luminance(base_color);
+        if l > 0.0 {
+            base_color / l
+        } else {
+            color!(1.0)
+        }
+    }
+
+    /// Fresnel of the specular lobe: Schlick towards the tinted specular
+    /// reflectance for opaque dielectrics, the exact dielectric Fresnel
+    /// for transmissive ones and Schlick towards the base color for metals.
+    #[inline(always)]
+    fn specular_fresnel(lobes: &Lobes, cos: f32, eta: f32) -> Color {
+        let opaque = schlick(lobes.specular_f0, cos);
+        let clear = color!(fresnel_dielectric(cos, eta));
+        let dielectric = Vec3::lerp(opaque, lobes.transmission_fraction, clear);
+        Vec3::lerp(dielectric, lobes.metallic, schlick(lobes.base_color, cos))
+    }
+
+    /// BSDF times cosine in the local frame.
+    #[inline(always)]
+    fn eval_local(&self, lobes: &Lobes, wo: Vec3, wi: Vec3, eta: f32) -> Color {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::ZERO;
+        }
+
+        if wi.z < 0.0 {
+            if lobes.transmission <= 0.0 {
+                return Color::ZERO;
+            }
+            let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+                return Color::ZERO;
+            };
+            let distribution = &lobes.specular_distribution;
+            let t = 1.0 - fresnel_dielectric(wo.dot(wm), eta);
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            let denom = denom * denom * wi.z * wo.z;
+            let f = t
+                * distribution.d(wm)
+                * distribution.g(wo, wi)
+                * (wi.dot(wm) * wo.dot(wm) / denom).abs()
+                / (etap * etap);
+            return lobes.base_color * (lobes.transmission * f * wi.z.abs());
+        }
+
+        let wh = wo + wi;
+        if wh.near_zero() {
+            return Color::ZERO;
+        }
+        let wh = wh.normalized();
+        let cos_d = wi.dot(wh);
+
+        let mut f = Color::ZERO;
+
+        if lobes.diffuse > 0.0 {
+            // Burley diffuse with its grazing retro-reflection, plus sheen.
+            let fl = schlick_weight(wi.z);
+            let fv = schlick_weight(wo.z);
+            let fd90 = 0.5 + 2.0 * lobes.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            let sheen = lobes.sheen * schlick_weight(cos_d);
+            f += (lobes.base_color * (fd / PI) + sheen) * lobes.diffuse;
+        }
+
+        let distribution = &lobes.specular_distribution;
+        let g = distribution.g(wo, wi);
+        f += Self::specular_fresnel(lobes, wo.dot(wh), eta)
+            * (distribution.d(wh) * g / (4.0 * wi.z * wo.z));
+
+        if lobes.clearcoat > 0.0 {
+            let distribution = &lobes.clearcoat_distribution;
+            let fc = 0.04 + 0.96 * schlick_weight(wo.dot(wh));
+            f += color!(
+                lobes.clearcoat * fc * distribution.d(wh) * distribution.g(wo, wi)
+                    / (4.0 * wi.z * wo.z)
+            );
+        }
+
+        f * wi.z
+    }
+
+    #[inline(always)]
+    fn pdf_local(&self, lobes: &Lobes, wo: Vec3, wi: Vec3, eta: f32) -> f32 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = lobes.pick;
+
+        if wi.z < 0.0 {
+            if p_transmission <= 0.0 {
+                return 0.0;
+            }
+            let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+                return 0.0;
+            };
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            let pdf =
+                lobes.specular_distribution.visible_d(wo, wm) * wi.dot(wm).abs() / (denom * denom);
+            return p_transmission * pdf;
+        }
+
+        let wh = wo + wi;
+        if wh.near_zero() {
+            return 0.0;
+        }
+        let wh = wh.normalized();
+        let jacobian = 1.0 / (4.0 * wo.dot(wh).abs());
+
+        p_diffuse * wi.z / PI
+            + p_specular * lobes.specular_distribution.visible_d(wo, wh) * jacobian
+            + p_clearcoat * lobes.clearcoat_distribution.visible_d(wo, wh) * jacobian
+    }
+}
+
+impl Material for Principled {
+    #[inline(always)]
+    fn sample(&self, wo: Vec3, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let frame = rec.shading_frame();
+        let wo = frame.to_local(wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(rec);
+        let eta = self.eta(rec);
+        let [p_diffuse, p_specular, p_clearcoat, _] = lobes.pick;
+
+        let u = rng.randomf32();
+        let wi = if u < p_diffuse {
+            let d = vec3!(0, 0, 1) + Vec3::random_unit_vector(rng);
+            if d.near_zero() {
+                vec3!(0, 0, 1)
+            } else {
+                d.normalized()
+            }
+        } else if u < p_diffuse + p_specular + p_clearcoat {
+            let distribution = if u < p_diffuse + p_specular {
+                &lobes.specular_distribution
+            } else {
+                &lobes.clearcoat_distribution
+            };
+            let wm = distribution.sample_wm(wo, rng.randomf32(), rng.randomf32());
+            reflect(wo, wm)
+        } else {
+            let wm = lobes
+                .specular_distribution
+                .sample_wm(wo, rng.randomf32(), rng.randomf32());
+            refract(wo, wm, eta)?.0
+        };
+
+        // Each lobe's direction is weighted against the density of all of
+        // them, which keeps the sampler consistent with `eval` and `pdf`.
+        let pdf = self.pdf_local(&lobes, wo, wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi: frame.to_world(wi),
+            weight: self.eval_local(&lobes, wo, wi, eta) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+
+    #[inline(always)]
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        let frame = rec.shading_frame();
+        self.eval_local(
+            &self.lobes(rec),
+            frame.to_local(wo),
+            frame.to_local(wi),
+            self.eta(rec),
+        )
+    }
+
+    #[inline(always)]
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> f32 {
+        let frame = rec.shading_frame();
+        self.pdf_local(
+            &self.lobes(rec),
+            frame.to_local(wo),
+            frame.to_local(wi),
+            self.eta(rec),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lobes_clamp_out_of_range_parameters() {
+        let material = Principled {
+            metallic: -0.5,
+            roughness: Arc::new(SolidColor::new(color!(3.0))),
+            transmission: 2.0,
+            clearcoat: 4.0,
+            clearcoat_gloss: -1.0,
+            ..Principled::default()
+        };
+        let lobes = material.lobes(&HitRecord::new());
+        assert!(lobes.pick.iter().all(|&p| (0.0..=1.0).contains(&p)));
+        assert!((lobes.pick.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert_eq!(lobes.roughness, 1.0);
+        assert_eq!(lobes.diffuse, 0.0);
+        assert_eq!(lobes.transmission, 1.0);
+        assert_eq!(lobes.clearcoat, 0.25);
+    }
+
+    #[test]
+    fn non_positive_ior_stays_finite() {
+        let mut rng = Rng::new(23);
+        for ior in [0.0, -1.0] {
+            let material = Principled {
+                roughness: Arc::new(SolidColor::new(color!(0.3))),
+                transmission: 1.0,
+                ior,
+                ..Principled::default()
+            };
+            for front_face in [true, false] {
+                let mut rec = HitRecord::new();
+                rec.normal = vec3!(0, 0, 1);
+                rec.geometric_normal = rec.normal;
+                rec.front_face = front_face;
+                for _ in 0..200 {
+                    let wo = Vec3::random_unit_vector(&mut rng);
+                    let wi = Vec3::random_unit_vector(&mut rng);
+                    let f = material.eval(wo, wi, &rec);
+                    assert!(f.x.is_finite() && f.y.is_finite() && f.z.is_finite());
+                    assert!(material.pdf(wo, wi, &rec).is_finite());
+                    if let Some(sample) = material.sample(wo, &rec, &mut rng) {
+                        assert!(sample.weight.x.is_finite() && sample.pdf.is_finite());
+                    }
+                }
+            }
+        }
+    }
+}
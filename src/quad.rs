@@ -0,0 +1,124 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+/// Parallelogram spanned by the edges `u` and `v` from the corner `q`. The
+/// surface coordinates run from 0 to 1 along each edge and the front side
+/// faces `u × v`.
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: MaterialId,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: MaterialId) -> Self {
+        Self { q, u, v, material }
+    }
+
+    /// Like `hit`, with the surface cut out where `alpha` says so and
+    /// `primitive` recorded as the primitive index, for shapes built from
+    /// several quads.
+    #[inline(always)]
+    pub fn hit_primitive(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+        primitive: u32,
+    ) -> bool {
+        let n = self.u.cross(self.v);
+        let denom = n.dot(r.direction);
+        if denom == 0.0 {
+            return false;
+        }
+        let t = n.dot(self.q - r.origin) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        // Coordinates of the hit point along the edges.
+        let p = r.at(t);
+        let w = n / n.lensq();
+        let planar = p - self.q;
+        let a = w.dot(planar.cross(self.v));
+        let b = w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return false;
+        }
+
+        record_hit(alpha, r, rec, |rec| {
+            rec.t = t;
+            rec.p = p;
+            rec.set_face_normal(r, n.normalized());
+            rec.u = a;
+            rec.v = b;
+            rec.dpdu = self.u;
+            rec.dpdv = self.v;
+            rec.material = self.material;
+            rec.primitive = primitive;
+        })
+    }
+}
+
+impl Hittable for Quad {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_primitive(r, t_min, t_max, None, rec, 0)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.q, self.q + self.u + self.v)
+            .grow(self.q + self.u)
+            .grow(self.q + self.v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_inside_and_misses_outside() {
+        let quad = Quad::new(vec3!(0, 0, 0), vec3!(2, 0, 0), vec3!(0, 1, 0), 3);
+        let mut rec = HitRecord::new();
+
+        let r = Ray::new(vec3!(1.5, 0.25, 2), vec3!(0, 0, -1));
+        assert!(quad.hit(r, 0.001, f32::MAX, &mut rec));
+        assert_eq!(rec.t, 2.0);
+        assert!(rec.front_face && rec.normal == vec3!(0, 0, 1));
+        assert_eq!((rec.u, rec.v), (0.75, 0.25));
+        assert_eq!(rec.material, 3);
+
+        // From behind, the normal faces the ray.
+        let r = Ray::new(vec3!(0.5, 0.5, -1), vec3!(0, 0, 1));
+        assert!(quad.hit(r, 0.001, f32::MAX, &mut rec));
+        assert!(!rec.front_face && rec.normal == vec3!(0, 0, -1));
+
+        for origin in [vec3!(2.5, 0.5, 1), vec3!(1, -0.1, 1), vec3!(-0.1, 0.5, 1)] {
+            let r = Ray::new(origin, vec3!(0, 0, -1));
+            assert!(!quad.hit(r, 0.001, f32::MAX, &mut rec));
+        }
+        // Parallel to the quad, and beyond t_max.
+        assert!(!quad.hit(
+            Ray::new(vec3!(-1, 0.5, 0), vec3!(1, 0, 0)),
+            0.001,
+            f32::MAX,
+            &mut rec
+        ));
+        assert!(!quad.hit(
+            Ray::new(vec3!(1, 0.5, 2), vec3!(0, 0, -1)),
+            0.001,
+            1.5,
+            &mut rec
+        ));
+    }
+}
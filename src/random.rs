@@ -1,7 +1,3 @@
-//static mut KEY: u64 = 8589392;
-static mut KEY: u32 = 8589392;
-static mut CTR: u64 = 0;
-
 #[inline(always)]
 fn xorshift32(state: &mut u32) -> u32 {
     let mut x = *state;
@@ -12,55 +8,60 @@ fn xorshift32(state: &mut u32) -> u32 {
     x
 }
 
+/// Integer hash (lowbias32 by Chris Wellons), used to turn seeds and pixel
+/// coordinates into well spread generator states.
 #[inline(always)]
-fn xorshift64(state: &mut u64) -> u64 {
-    let mut x = *state;
-    x ^= x << 13;
-    x ^= x >> 7;
-    x ^= x << 17;
-    *state = x;
+fn hash32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
     x
 }
 
+/// Number in [0, 1) that only depends on `values`, for random decisions that
+/// have to come out the same whenever they are made for the same input.
 #[inline(always)]
-fn squares64(ctr: u64, key: u64) -> u64 {
-    let mut t = 0u64;
-    let mut x = 0u64;
-    let mut y = 0u64;
-    let mut z = 0u64;
-    x = ctr * key;
-    y = x;
-    z = y + key;
-    x = x * x + y;
-    x = (x >> 32) | (x << 32); /* round 1 */
-    x = x * x + z;
-    x = (x >> 32) | (x << 32); /* round 2 */
-    x = x * x + y;
-    x = (x >> 32) | (x << 32); /* round 3 */
-    x = x * x + z;
-    t = x;
-    x = (x >> 32) | (x << 32); /* round 4 */
-    return t ^ ((x * x + y) >> 32); /* round 5 */
+pub fn hash_floats(values: &[f32]) -> f32 {
+    let mut h = 0;
+    for v in values {
+        h = hash32(h ^ v.to_bits());
+    }
+    (h >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
 }
 
-#[inline(always)]
-pub fn set_random_seed(seed: u32) {
-    unsafe {
-        KEY = seed;
-    }
+/// Random number generator state. Every render thread (or pixel) owns its
+/// own, so results only depend on the seed and not on scheduling.
+#[derive(Clone)]
+pub struct Rng {
+    state: u32,
 }
 
-#[inline(always)]
-pub fn randomf32() -> f32 {
-    unsafe {
-        //CTR += 1;
-        //squares64(CTR, KEY) as f32 / (u64::MAX as f32 + 1.0)
-        //xorshift64(&mut KEY) as f32 / (u64::MAX as f32 + 1.0)
-        xorshift32(&mut KEY) as f32 / (u32::MAX as f32 + 1.0)
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // xorshift never leaves the all-zero state.
+        let state = hash32(seed);
+        Self {
+            state: if state == 0 { 1 } else { state },
+        }
     }
-}
 
-#[inline(always)]
-pub fn randomf32_range(min: f32, max: f32) -> f32 {
-    min + (max - min) * randomf32()
+    /// Generator for a single pixel, independent of which thread renders it
+    /// and in which order.
+    pub fn for_pixel(seed: u32, x: u32, y: u32) -> Self {
+        Self::new(hash32(seed ^ hash32(x ^ hash32(y))))
+    }
+
+    #[inline(always)]
+    pub fn randomf32(&mut self) -> f32 {
+        // Use the top 24 bits so the result is exactly representable and
+        // always below 1.0.
+        (xorshift32(&mut self.state) >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    #[inline(always)]
+    pub fn randomf32_range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.randomf32()
+    }
 }
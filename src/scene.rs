@@ -0,0 +1,1509 @@
+//! Text scene description.
+//!
+//! A scene file is a list of statements. `#` starts a comment that runs to
+//! the end of the line, strings are double quoted and blocks are a list of
+//! `property value...` pairs between braces:
+//!
+//! ```text
+//! include "materials.scene"          # path relative to this file
+//!
+//! film    { width 1200 height 800 crop 0 1 0 1 }  # crop: x0 x1 y0 y1
+//! sampler { spp 512 bounces 8 seed 8589392 }
+//! render  { threads 12 tile 64 }
+//! tonemap { operator aces exposure 0.5 white 4 }  # exposure in stops
+//!
+//! camera {
+//!     from 13 2 3                    # position
+//!     at 0 0 0                       # look-at point
+//!     up 0 1 0
+//!     fov 20                         # vertical field of view in degrees
+//!     aperture 0.1
+//!     focus 10                       # defaults to the distance to `at`
+//! }
+//!
+//! sky { horizon 1 1 1 zenith 0.5 0.7 1 }
+//!
+//! # Equirectangular .hdr or .exr image instead of the sky; rotation turns
+//! # it around the up axis, in degrees.
+//! environment { file "studio.hdr" intensity 1 rotation 90 }
+//!
+//! # Or a daylight sky with a matching sun. Angles in degrees, azimuth
+//! # from -z towards +x; turbidity from 2 (clear) to 10 (hazy).
+//! physical_sky { elevation 30 azimuth 45 turbidity 3 intensity 1 sun_size 0.53 }
+//!
+//! # Textures. Wherever a texture is accepted, a color works too. Checkers
+//! # in world space are 3D cells; images are png, bmp, hdr or exr, wrap is
+//! # repeat, clamp or mirror and filter bilinear or nearest. Data such as
+//! # normal maps needs `color_space linear` instead of the default srgb;
+//! # `channel alpha` reads a PNG's alpha channel.
+//! texture red   solid    { color 0.8 0.1 0.1 }
+//! texture tiles checker  { even 0.9 0.9 0.9 odd red scale 10 space uv }
+//! texture fade  gradient { from 0 0 0 to 1 1 1 axis v }
+//! texture wood  image    { file "wood.png" wrap repeat filter bilinear }
+//! texture bumps image    { file "bumps.png" color_space linear }
+//! texture mask  image    { file "leaf.png" channel alpha }
+//!
+//! # Seeded procedural noise, in world space unless `space uv` is given.
+//! # Patterns: fbm, turbulence, marble, wood, cells and cracks (Worley);
+//! # basis perlin or simplex. Number inputs like roughness take textures.
+//! texture veins noise { pattern marble basis perlin scale 2 octaves 6
+//!                       seed 7 from 0.2 0.2 0.25 to 0.95 0.95 0.9 }
+//!
+//! material ground lambertian { albedo 0.5 0.5 0.5 }
+//! material floor  lambertian { albedo tiles }
+//! material steel  metal      { albedo 0.7 0.6 0.5 roughness 0.3 }
+//! material gold   metal      { preset gold roughness 0.2 }  # or: eta and k,
+//!                                          # aluminium, copper, silver
+//! material glass  dielectric { ior 1.5 roughness 0 }
+//! material lamp   emissive   { color 1 0.9 0.8 strength 4 }
+//!
+//! # Disney-style material; all properties are optional and, apart from
+//! # base_color and ior, between 0 and 1.
+//! material paint principled {
+//!     base_color 0.8 0.1 0.1  metallic 0  roughness 0.5
+//!     specular 0.5  specular_tint 0  anisotropic 0
+//!     sheen 0  sheen_tint 0.5  clearcoat 1  clearcoat_gloss 0.9
+//!     transmission 0  ior 1.5
+//! }
+//!
+//! # Any material but emissive takes either a tangent space normal map or
+//! # a height texture for bump mapping, scaled to world units.
+//! material tiled principled { base_color 0.7 0.2 0.1 normal_map bumps }
+//! material stone lambertian { albedo 0.6 0.6 0.6 bump veins bump_scale 0.02 }
+//!
+//! # They also take an opacity: rays, shadow rays included, pass through
+//! # where it is below the threshold, or with probability 1 - alpha when
+//! # the mode is stochastic.
+//! material leaf lambertian { albedo 0.2 0.5 0.1 alpha mask alpha_threshold 0.5 }
+//! material veil principled { alpha 0.3 alpha_mode stochastic }
+//!
+//! sphere   { center 0 -1000 0 radius 1000 material ground }
+//! triangle { v0 -1 0 0 v1 1 0 0 v2 0 1 0 material steel }
+//! mesh     { file "bunny.obj" material glass }  # material is used where
+//!                                               # the MTL file has none
+//!
+//! # Parallelogram from a corner along two edges; the normal is u x v and
+//! # uv runs from 0 to 1 along them. Disks face their normal, planes are
+//! # infinite and boxes are axis-aligned with outward facing sides. Emissive
+//! # quads, disks and boxes are sampled as area lights; planes are not.
+//! quad  { corner -1 2 -1 u 2 0 0 v 0 0 2 material lamp }
+//! disk  { center 0 3 0 normal 0 -1 0 radius 0.5 material lamp }
+//! plane { point 0 0 0 normal 0 1 0 material floor }
+//! box   { min -1 0 -1 max 1 2 1 material paint }
+//!
+//! # Lights without geometry; intensity scales color. Angles are full
+//! # apex angles in degrees: the spot cone (below 180, default 60) fades out
+//! # over `falloff` degrees inside its edge, and a directional light with a
+//! # non-zero angle is a sun disk of that angular diameter.
+//! light point       { position 0 5 0 color 1 1 1 intensity 50 }
+//! light spot        { position 0 5 0 at 0 0 0 intensity 80 angle 60 falloff 5 }
+//! light directional { direction -1 -2 -1 intensity 3 angle 0.53 }
+//! ```
+//!
+//! Every statement except `texture`, `material`, `light` and the shapes may
+//! appear at most once per scene; properties that are left out keep their
+//! defaults. Textures and materials must be declared before they are used.
+
+use crate::alpha::*;
+use crate::camera::*;
+use crate::cuboid::*;
+use crate::disk::*;
+use crate::environment::*;
+use crate::hittable_list::*;
+use crate::image::*;
+use crate::light::*;
+use crate::material::*;
+use crate::noise::*;
+use crate::normal_map::*;
+use crate::obj::*;
+use crate::physical_sky::*;
+use crate::plane::*;
+use crate::principled::*;
+use crate::quad::*;
+use crate::sky::*;
+use crate::sphere::*;
+use crate::texture::*;
+use crate::tonemap::*;
+use crate::triangle::*;
+use crate::vec3::*;
+use crate::RaytracerConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file.display(),
+                self.line,
+                self.column,
+                self.message
+            )
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub world: HittableList,
+    pub background: Background,
+    pub lights: Vec<Light>,
+    pub config: RaytracerConfig,
+}
+
+#[derive(Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(f32),
+    /// Literal without a fraction or exponent, kept exact for counts and
+    /// seeds.
+    Integer(i64),
+    Str(String),
+    LBrace,
+    RBrace,
+    Eof,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Ident(s) => write!(f, "'{}'", s),
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::Integer(n) => write!(f, "number {}", n),
+            TokenKind::Str(s) => write!(f, "string \"{}\"", s),
+            TokenKind::LBrace => write!(f, "'{{'"),
+            TokenKind::RBrace => write!(f, "'}}'"),
+            TokenKind::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn lex(file: &Path, source: &str) -> Result<Vec<Token>, SceneError> {
+    let error = |line, column, message: String| SceneError {
+        file: file.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    let mut line = 1;
+    let mut column = 1;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let (start_line, start_column) = (line, column);
+
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            column += 1;
+            continue;
+        }
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let kind = if c == '{' || c == '}' {
+            i += 1;
+            column += 1;
+            if c == '{' {
+                TokenKind::LBrace
+            } else {
+                TokenKind::RBrace
+            }
+        } else if c == '"' {
+            let mut s = String::new();
+            i += 1;
+            column += 1;
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') => {
+                        return Err(error(
+                            start_line,
+                            start_column,
+                            "unterminated string".into(),
+                        ));
+                    }
+                    Some('"') => break,
+                    Some('\\') if matches!(chars.get(i + 1), Some('"') | Some('\\')) => {
+                        s.push(chars[i + 1]);
+                        i += 2;
+                        column += 2;
+                    }
+                    Some(&c) => {
+                        s.push(c);
+                        i += 1;
+                        column += 1;
+                    }
+                }
+            }
+            i += 1;
+            column += 1;
+            TokenKind::Str(s)
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '-' | '+' | '.'))
+            {
+                i += 1;
+            }
+            column += i - start;
+            let text: String = chars[start..i].iter().collect();
+            match (text.parse::<i64>(), text.parse::<f32>()) {
+                (Ok(n), _) => TokenKind::Integer(n),
+                (_, Ok(n)) => TokenKind::Number(n),
+                _ => {
+                    return Err(error(
+                        start_line,
+                        start_column,
+                        format!("invalid number '{}'", text),
+                    ));
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '-' | '.' | ':'))
+            {
+                i += 1;
+            }
+            column += i - start;
+            TokenKind::Ident(chars[start..i].iter().collect())
+        } else {
+            return Err(error(
+                start_line,
+                start_column,
+                format!("unexpected character '{}'", c),
+            ));
+        };
+
+        tokens.push(Token {
+            kind,
+            line: start_line,
+            column: start_column,
+        });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        line,
+        column,
+    });
+
+    Ok(tokens)
+}
+
+/// State shared by the scene file and everything it includes.
+struct SceneBuilder {
+    camera: CameraSettings,
+    background: Background,
+    lights: Vec<Light>,
+    config: RaytracerConfig,
+    materials: HashMap<String, MaterialId>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    world: HittableList,
+    seen: Vec<String>,
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error_at(&self, token: &Token, message: impl Into<String>) -> SceneError {
+        SceneError {
+            file: self.file.to_path_buf(),
+            line: token.line,
+            column: token.column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), SceneError> {
+        let token = self.next();
+        if token.kind != kind {
+            return Err(self.error_at(&token, format!("expected {}, found {}", kind, token.kind)));
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<(String, Token), SceneError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Ident(s) => Ok((s.clone(), token)),
+            kind => Err(self.error_at(&token, format!("expected a name, found {}", kind))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SceneError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Str(s) => Ok(s.clone()),
+            kind => Err(self.error_at(&token, format!("expected a string, found {}", kind))),
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, SceneError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Number(n) => Ok(*n),
+            TokenKind::Integer(n) => Ok(*n as f32),
+            kind => Err(self.error_at(&token, format!("expected a number, found {}", kind))),
+        }
+    }
+
+    fn positive(&mut self) -> Result<f32, SceneError> {
+        let token = self.peek().clone();
+        let n = self.number()?;
+        if n <= 0.0 {
+            return Err(self.error_at(&token, "expected a positive number"));
+        }
+        Ok(n)
+    }
+
+    /// Number in [0, 1], for weights and other fractions.
+    fn unit(&mut self) -> Result<f32, SceneError> {
+        let token = self.peek().clone();
+        let n = self.number()?;
+        if !(0.0..=1.0).contains(&n) {
+            return Err(self.error_at(&token, "expected a number between 0 and 1"));
+        }
+        Ok(n)
+    }
+
+    /// Integer in `min..=u32::MAX`, read exactly rather than through f32.
+    fn integer(&mut self, min: u32, what: &str) -> Result<u32, SceneError> {
+        let token = self.next();
+        match &token.kind {
+            &TokenKind::Integer(n) if n >= min as i64 && n <= u32::MAX as i64 => Ok(n as u32),
+            kind => Err(self.error_at(&token, format!("expected {}, found {}", what, kind))),
+        }
+    }
+
+    fn count(&mut self) -> Result<u32, SceneError> {
+        self.integer(1, "a positive integer")
+    }
+
+    fn seed(&mut self) -> Result<u32, SceneError> {
+        self.integer(0, "a seed between 0 and 4294967295")
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, SceneError> {
+        Ok(vec3!(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// Iterates the properties of a `{ ... }` block, handing each property
+    /// name to `property`, which must consume its values.
+    fn block<F>(&mut self, mut property: F) -> Result<(), SceneError>
+    where
+        F: FnMut(&mut Self, &str, &Token) -> Result<(), SceneError>,
+    {
+        self.expect(TokenKind::LBrace)?;
+        loop {
+            if self.peek().kind == TokenKind::RBrace {
+                self.next();
+                return Ok(());
+            }
+            let (name, token) = self.ident()?;
+            property(self, &name, &token)?;
+        }
+    }
+
+    fn unknown_property(&self, token: &Token, block: &str, name: &str) -> SceneError {
+        self.error_at(token, format!("unknown {} property '{}'", block, name))
+    }
+
+    /// Name out of a fixed set, mapped with `from_name`.
+    fn choice<T>(
+        &mut self,
+        what: &str,
+        names: &str,
+        from_name: impl Fn(&str) -> Option<T>,
+    ) -> Result<T, SceneError> {
+        let (name, token) = self.ident()?;
+        from_name(&name).ok_or_else(|| {
+            self.error_at(
+                &token,
+                format!("unknown {} '{}', expected one of {}", what, name, names),
+            )
+        })
+    }
+
+    /// Either the name of a texture or a constant color.
+    fn texture(&mut self, builder: &SceneBuilder) -> Result<Arc<dyn Texture>, SceneError> {
+        if !matches!(self.peek().kind, TokenKind::Ident(_)) {
+            return Ok(Arc::new(SolidColor::new(self.vec3()?)));
+        }
+        let (name, token) = self.ident()?;
+        match builder.textures.get(&name) {
+            Some(t) => Ok(t.clone()),
+            None => Err(self.error_at(&token, format!("unknown texture '{}'", name))),
+        }
+    }
+
+    /// Either the name of a texture or a constant, for single channel
+    /// inputs.
+    fn scalar_texture(&mut self, builder: &SceneBuilder) -> Result<Arc<dyn Texture>, SceneError> {
+        if matches!(
+            self.peek().kind,
+            TokenKind::Number(_) | TokenKind::Integer(_)
+        ) {
+            let value = self.number()?;
+            return Ok(Arc::new(SolidColor::new(color!(value))));
+        }
+        self.texture(builder)
+    }
+
+    /// Like `scalar_texture`, but keeps constants apart so that smooth
+    /// metals and glass stay purely specular.
+    fn roughness(&mut self, builder: &SceneBuilder) -> Result<Roughness, SceneError> {
+        if matches!(
+            self.peek().kind,
+            TokenKind::Number(_) | TokenKind::Integer(_)
+        ) {
+            return Ok(Roughness::Constant(self.number()?));
+        }
+        Ok(Roughness::Textured(self.texture(builder)?))
+    }
+
+    fn material(&mut self, builder: &SceneBuilder) -> Result<(MaterialId, Token), SceneError> {
+        let (name, token) = self.ident()?;
+        match builder.materials.get(&name) {
+            Some(m) => Ok((*m, token)),
+            None => Err(self.error_at(&token, format!("unknown material '{}'", name))),
+        }
+    }
+
+    fn parse(&mut self, builder: &mut SceneBuilder, depth: usize) -> Result<(), SceneError> {
+        loop {
+            let token = self.next();
+            let keyword = match &token.kind {
+                TokenKind::Eof => return Ok(()),
+                TokenKind::Ident(s) => s.clone(),
+                kind => {
+                    return Err(
+                        self.error_at(&token, format!("expected a statement, found {}", kind))
+                    );
+                }
+            };
+
+            match keyword.as_str() {
+                "include" => self.parse_include(builder, depth, &token)?,
+                "film" | "sampler" | "render" | "tonemap" | "camera" | "sky" | "environment"
+                | "physical_sky" => {
+                    if builder.seen.contains(&keyword) {
+                        return Err(
+                            self.error_at(&token, format!("duplicate '{}' statement", keyword))
+                        );
+                    }
+                    const BACKGROUNDS: [&str; 3] = ["sky", "environment", "physical_sky"];
+                    if BACKGROUNDS.contains(&keyword.as_str())
+                        && builder
+                            .seen
+                            .iter()
+                            .any(|s| BACKGROUNDS.contains(&s.as_str()))
+                    {
+                        return Err(self.error_at(
+                            &token,
+                            "only one of 'sky', 'environment' and 'physical_sky' may be given",
+                        ));
+                    }
+                    builder.seen.push(keyword.clone());
+                    match keyword.as_str() {
+                        "film" => self.parse_film(builder)?,
+                        "sampler" => self.parse_sampler(builder)?,
+                        "render" => self.parse_render(builder)?,
+                        "tonemap" => self.parse_tonemap(builder)?,
+                        "camera" => self.parse_camera(builder)?,
+                        "sky" => self.parse_sky(builder)?,
+                        "physical_sky" => self.parse_physical_sky(builder)?,
+                        _ => self.parse_environment(builder, &token)?,
+                    }
+                }
+                "texture" => self.parse_texture(builder, &token)?,
+                "material" => self.parse_material(builder)?,
+                "sphere" => self.parse_sphere(builder, &token)?,
+                "triangle" => self.parse_triangle(builder, &token)?,
+                "mesh" => self.parse_mesh(builder, &token)?,
+                "quad" => self.parse_quad(builder, &token)?,
+                "disk" => self.parse_disk(builder, &token)?,
+                "plane" => self.parse_plane(builder, &token)?,
+                "box" => self.parse_box(builder, &token)?,
+                "light" => self.parse_light(builder)?,
+                _ => return Err(self.error_at(&token, format!("unknown statement '{}'", keyword))),
+            }
+        }
+    }
+
+    fn parse_include(
+        &mut self,
+        builder: &mut SceneBuilder,
+        depth: usize,
+        token: &Token,
+    ) -> Result<(), SceneError> {
+        let name = self.string()?;
+        if depth + 1 >= MAX_INCLUDE_DEPTH {
+            return Err(self.error_at(token, "includes nested too deeply (recursive include?)"));
+        }
+        let dir = self.file.parent().unwrap_or(Path::new(""));
+        let path = dir.join(name);
+        let source = fs::read_to_string(&path).map_err(|e| {
+            self.error_at(token, format!("cannot read '{}': {}", path.display(), e))
+        })?;
+        parse_source(&path, &source, builder, depth + 1)
+    }
+
+    fn parse_film(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        self.block(|p, name, token| {
+            match name {
+                "width" => builder.config.film.width = p.count()?,
+                "height" => builder.config.film.height = p.count()?,
+                "crop" => {
+                    let [x0, x1, y0, y1] = [p.number()?, p.number()?, p.number()?, p.number()?];
+                    builder.config.film = builder
+                        .config
+                        .film
+                        .with_crop_window(x0, x1, y0, y1)
+                        .ok_or_else(|| {
+                            p.error_at(token, "crop window must be within [0, 1] and not empty")
+                        })?;
+                }
+                _ => return Err(p.unknown_property(token, "film", name)),
+            }
+            Ok(())
+        })
+    }
+
+    fn parse_sampler(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        self.block(|p, name, token| {
+            match name {
+                "spp" => builder.config.samples_per_pixel = p.count()?,
+                "bounces" => builder.config.max_bounces = p.count()?,
+                "seed" => builder.config.seed = p.seed()?,
+                _ => return Err(p.unknown_property(token, "sampler", name)),
+            }
+            Ok(())
+        })
+    }
+
+    fn parse_render(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        self.block(|p, name, token| {
+            match name {
+                "threads" => builder.config.max_threads = p.count()?,
+                "tile" => builder.config.tile_size = p.count()?,
+                _ => return Err(p.unknown_property(token, "render", name)),
+            }
+            Ok(())
+        })
+    }
+
+    fn parse_tonemap(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let tone_mapping = &mut builder.config.tone_mapping;
+        self.block(|p, name, token| {
+            match name {
+                "operator" => {
+                    let (operator, token) = p.ident()?;
+                    tone_mapping.operator =
+                        ToneMapOperator::from_name(&operator).ok_or_else(|| {
+                            p.error_at(
+                                &token,
+                                format!(
+                                    "unknown tone mapping operator '{}', expected one of {}",
+                                    operator,
+                                    ToneMapOperator::NAMES
+                                ),
+                            )
+                        })?;
+                }
+                "exposure" => tone_mapping.exposure = p.number()?,
+                "white" => tone_mapping.white_point = Some(p.positive()?),
+                _ => return Err(p.unknown_property(token, "tonemap", name)),
+            }
+            Ok(())
+        })
+    }
+
+    fn parse_camera(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let camera = &mut builder.camera;
+        self.block(|p, name, token| {
+            match name {
+                "from" => camera.lookfrom = p.vec3()?,
+                "at" => camera.lookat = p.vec3()?,
+                "up" => camera.vup = p.vec3()?,
+                "fov" => camera.vfov = p.positive()?,
+                "aperture" => camera.aperture = p.number()?,
+                "focus" => camera.focus_dist = Some(p.positive()?),
+                _ => return Err(p.unknown_property(token, "camera", name)),
+            }
+            Ok(())
+        })
+    }
+
+    fn parse_sky(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let mut sky = Sky::default();
+        self.block(|p, name, token| {
+            match name {
+                "horizon" => sky.horizon = p.vec3()?,
+                "zenith" => sky.zenith = p.vec3()?,
+                _ => return Err(p.unknown_property(token, "sky", name)),
+            }
+            Ok(())
+        })?;
+        builder.background = Background::Sky(sky);
+        Ok(())
+    }
+
+    fn parse_physical_sky(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let mut elevation = 45.0;
+        let mut azimuth = 0.0;
+        let mut turbidity = 3.0;
+        let mut intensity = 1.0;
+        let mut sun_size = 0.53;
+        self.block(|p, name, token| {
+            match name {
+                "elevation" => {
+                    elevation = p.number()?;
+                    if !(0.0..=90.0).contains(&elevation) {
+                        return Err(p.error_at(token, "elevation must be within [0, 90] degrees"));
+                    }
+                }
+                "azimuth" => azimuth = p.number()?,
+                "turbidity" => {
+                    turbidity = p.number()?;
+                    if !(1.7..=10.0).contains(&turbidity) {
+                        return Err(p.error_at(token, "turbidity must be within [1.7, 10]"));
+                    }
+                }
+                "intensity" => intensity = p.number()?,
+                "sun_size" => sun_size = f32::max(p.number()?, 0.0),
+                _ => return Err(p.unknown_property(token, "physical_sky", name)),
+            }
+            Ok(())
+        })?;
+
+        let mut sky = PhysicalSky::new(elevation, azimuth, turbidity, intensity);
+        sky.sun_size = sun_size;
+        builder.background = Background::PhysicalSky(sky);
+        Ok(())
+    }
+
+    fn parse_environment(
+        &mut self,
+        builder: &mut SceneBuilder,
+        token: &Token,
+    ) -> Result<(), SceneError> {
+        let mut file = None;
+        let mut intensity = 1.0;
+        let mut rotation = 0.0;
+        self.block(|p, name, token| {
+            match name {
+                "file" => file = Some(p.string()?),
+                "intensity" => intensity = p.number()?,
+                "rotation" => rotation = p.number()?,
+                _ => return Err(p.unknown_property(token, "environment", name)),
+            }
+            Ok(())
+        })?;
+
+        let file = file.ok_or_else(|| self.error_at(token, "environment has no file"))?;
+        let path = self.file.parent().unwrap_or(Path::new("")).join(file);
+        let image = load_hdr_image(&path).map_err(|e| {
+            self.error_at(
+                token,
+                format!("failed to load environment '{}': {}", path.display(), e),
+            )
+        })?;
+        builder.background =
+            Background::Environment(Arc::new(Environment::new(image, intensity, rotation)));
+        Ok(())
+    }
+
+    fn parse_texture(
+        &mut self,
+        builder: &mut SceneBuilder,
+        token: &Token,
+    ) -> Result<(), SceneError> {
+        let (name, name_token) = self.ident()?;
+        if builder.textures.contains_key(&name) {
+            return Err(self.error_at(&name_token, format!("texture '{}' already defined", name)));
+        }
+
+        let (kind, kind_token) = self.ident()?;
+        if !matches!(
+            kind.as_str(),
+            "solid" | "checker" | "gradient" | "image" | "noise"
+        ) {
+            return Err(self.error_at(&kind_token, format!("unknown texture type '{}'", kind)));
+        }
+
+        let white: Arc<dyn Texture> = Arc::new(SolidColor::new(color!(1.0)));
+        let black: Arc<dyn Texture> = Arc::new(SolidColor::new(color!(0.0)));
+        let mut color = color!(1.0);
+        let mut even = white;
+        let mut odd = black;
+        let mut scale = 1.0;
+        let mut space = TextureSpace::Uv;
+        let mut from = color!(0.0);
+        let mut to = color!(1.0);
+        let mut axis = GradientAxis::V;
+        let mut file = None;
+        let mut wrap = WrapMode::Repeat;
+        let mut filter = Filter::Bilinear;
+        let mut color_space = ColorSpace::Srgb;
+        let mut channel = ImageChannel::Color;
+        let mut pattern = NoisePattern::Fbm;
+        let mut basis = NoiseBasis::Perlin;
+        let mut octaves = 6;
+        let mut seed = 1;
+        if kind == "noise" {
+            space = TextureSpace::World;
+        }
+        self.block(|p, property, token| {
+            match (kind.as_str(), property) {
+                ("solid", "color") => color = p.vec3()?,
+                ("checker", "even") => even = p.texture(builder)?,
+                ("checker", "odd") => odd = p.texture(builder)?,
+                ("checker" | "noise", "scale") => scale = p.positive()?,
+                ("checker" | "noise", "space") => {
+                    space = p.choice(
+                        "texture space",
+                        TextureSpace::NAMES,
+                        TextureSpace::from_name,
+                    )?
+                }
+                ("gradient" | "noise", "from") => from = p.vec3()?,
+                ("gradient" | "noise", "to") => to = p.vec3()?,
+                ("noise", "pattern") => {
+                    pattern = p.choice(
+                        "noise pattern",
+                        NoisePattern::NAMES,
+                        NoisePattern::from_name,
+                    )?
+                }
+                ("noise", "basis") => {
+                    basis = p.choice("noise basis", NoiseBasis::NAMES, NoiseBasis::from_name)?
+                }
+                ("noise", "octaves") => octaves = p.count()?,
+                ("noise", "seed") => seed = p.seed()?,
+                ("gradient", "axis") => {
+                    axis = p.choice(
+                        "gradient axis",
+                        GradientAxis::NAMES,
+                        GradientAxis::from_name,
+                    )?
+                }
+                ("image", "file") => file = Some(p.string()?),
+                ("image", "wrap") => {
+                    wrap = p.choice("wrap mode", WrapMode::NAMES, WrapMode::from_name)?
+                }
+                ("image", "filter") => {
+                    filter = p.choice("filter", Filter::NAMES, Filter::from_name)?
+                }
+                ("image", "channel") => {
+                    channel = p.choice(
+                        "image channel",
+                        ImageChannel::NAMES,
+                        ImageChannel::from_name,
+                    )?
+                }
+                ("image", "color_space") => {
+                    color_space =
+                        p.choice("color space", ColorSpace::NAMES, ColorSpace::from_name)?
+                }
+                _ => return Err(p.unknown_property(token, &kind, property)),
+            }
+            Ok(())
+        })?;
+
+        let texture: Arc<dyn Texture> = match kind.as_str() {
+            "solid" => Arc::new(SolidColor::new(color)),
+            "checker" => Arc::new(Checker {
+                even,
+                odd,
+                scale,
+                space,
+            }),
+            "gradient" => Arc::new(Gradient { from, to, axis }),
+            "noise" => Arc::new(NoiseTexture {
+                noise: Noise::new(seed),
+                basis,
+                pattern,
+                scale,
+                octaves,
+                space,
+                from,
+                to,
+            }),
+            _ => {
+                let file = file.ok_or_else(|| self.error_at(token, "image texture has no file"))?;
+                let path = self.file.parent().unwrap_or(Path::new("")).join(file);
+                let image = match channel {
+                    ImageChannel::Color => load_image(&path, color_space),
+                    ImageChannel::Alpha => load_image_alpha(&path),
+                };
+                let image = image.map_err(|e| {
+                    self.error_at(
+                        token,
+                        format!("failed to load texture '{}': {}", path.display(), e),
+                    )
+                })?;
+                Arc::new(ImageTexture::new(Arc::new(image), wrap, filter))
+            }
+        };
+        builder.textures.insert(name, texture);
+        Ok(())
+    }
+
+    fn parse_material(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let (name, name_token) = self.ident()?;
+        if builder.materials.contains_key(&name) {
+            return Err(self.error_at(&name_token, format!("material '{}' already defined", name)));
+        }
+
+        let (kind, kind_token) = self.ident()?;
+        if !matches!(
+            kind.as_str(),
+            "lambertian" | "metal" | "dielectric" | "emissive" | "principled"
+        ) {
+            return Err(self.error_at(&kind_token, format!("unknown material type '{}'", kind)));
+        }
+
+        let mut albedo = color!(0.8);
+        let mut texture = None;
+        let mut roughness = Roughness::Constant(0.0);
+        let mut eta = None;
+        let mut k = None;
+        let mut ior = 1.5;
+        let mut emission = color!(1.0);
+        let mut strength = 1.0;
+        let mut principled = Principled::default();
+        let mut normal_map = None;
+        let mut bump = None;
+        let mut bump_scale = 1.0;
+        let mut alpha = None;
+        let mut alpha_mode = AlphaMode::Threshold;
+        let mut alpha_threshold = 0.5;
+        self.block(|p, property, token| {
+            match (kind.as_str(), property) {
+                ("lambertian", "albedo") => texture = Some(p.texture(builder)?),
+                ("metal", "albedo") => albedo = p.vec3()?,
+                ("metal", "preset") => {
+                    let (preset, token) = p.ident()?;
+                    let (e, k_) = conductor_preset(&preset).ok_or_else(|| {
+                        p.error_at(&token, format!("unknown metal preset '{}'", preset))
+                    })?;
+                    eta = Some(e);
+                    k = Some(k_);
+                }
+                ("metal", "eta") => eta = Some(p.vec3()?),
+                ("metal", "k") => k = Some(p.vec3()?),
+                ("metal" | "dielectric", "roughness") => roughness = p.roughness(builder)?,
+                ("dielectric", "ior") => ior = p.positive()?,
+                ("emissive", "color") => emission = p.vec3()?,
+                ("emissive", "strength") => strength = p.number()?,
+                ("principled", "base_color") => principled.base_color = p.texture(builder)?,
+                ("principled", "metallic") => principled.metallic = p.unit()?,
+                ("principled", "roughness") => principled.roughness = p.scalar_texture(builder)?,
+                ("principled", "specular") => principled.specular = p.unit()?,
+                ("principled", "specular_tint") => principled.specular_tint = p.unit()?,
+                ("principled", "anisotropic") => principled.anisotropic = p.unit()?,
+                ("principled", "sheen") => principled.sheen = p.unit()?,
+                ("principled", "sheen_tint") => principled.sheen_tint = p.unit()?,
+                ("principled", "clearcoat") => principled.clearcoat = p.unit()?,
+                ("principled", "clearcoat_gloss") => principled.clearcoat_gloss = p.unit()?,
+                ("principled", "transmission") => principled.transmission = p.unit()?,
+                ("principled", "ior") => principled.ior = p.positive()?,
+                (kind, "normal_map") if kind != "emissive" => {
+                    normal_map = Some(p.texture(builder)?)
+                }
+                (kind, "bump") if kind != "emissive" => bump = Some(p.scalar_texture(builder)?),
+                (kind, "bump_scale") if kind != "emissive" => bump_scale = p.number()?,
+                (kind, "alpha") if kind != "emissive" => alpha = Some(p.scalar_texture(builder)?),
+                (kind, "alpha_mode") if kind != "emissive" => {
+                    alpha_mode = p.choice("alpha mode", AlphaMode::NAMES, AlphaMode::from_name)?
+                }
+                (kind, "alpha_threshold") if kind != "emissive" => alpha_threshold = p.number()?,
+                _ => return Err(p.unknown_property(token, &kind, property)),
+            }
+            Ok(())
+        })?;
+
+        let material: Box<dyn Material> = match kind.as_str() {
+            "lambertian" => match texture {
+                Some(texture) => Box::new(Lambertian::textured(texture)),
+                None => Box::new(Lambertian::new(albedo)),
+            },
+            "metal" => match (eta, k) {
+                (Some(eta), Some(k)) => Box::new(Conductor::new(eta, k, roughness)),
+                (None, None) => Box::new(Conductor::from_reflectance(albedo, roughness)),
+                _ => return Err(self.error_at(&name_token, "metal needs both eta and k")),
+            },
+            "dielectric" => Box::new(Dieletric::new(ior, roughness)),
+            "principled" => Box::new(principled),
+            _ => Box::new(Emissive::new(emission, strength)),
+        };
+        let material: Box<dyn Material> = match (normal_map, bump) {
+            (None, None) => material,
+            (Some(texture), None) => Box::new(NormalMapped::new(
+                material,
+                NormalPerturbation::NormalMap(texture),
+            )),
+            (None, Some(height)) => Box::new(NormalMapped::new(
+                material,
+                NormalPerturbation::Bump {
+                    height,
+                    scale: bump_scale,
+                },
+            )),
+            (Some(_), Some(_)) => {
+                return Err(self.error_at(&name_token, "material has both a normal map and a bump"))
+            }
+        };
+        let id = builder.world.add_material(material);
+        if let Some(alpha) = alpha {
+            let mask = AlphaMask::new(alpha, alpha_mode, alpha_threshold);
+            builder
+                .world
+                .materials
+                .set_alpha(id, mask)
+                .map_err(|e| self.error_at(&name_token, e))?;
+        }
+        builder.materials.insert(name, id);
+        Ok(())
+    }
+
+    fn parse_light(&mut self, builder: &mut SceneBuilder) -> Result<(), SceneError> {
+        let (kind, kind_token) = self.ident()?;
+        if !matches!(kind.as_str(), "point" | "spot" | "directional") {
+            return Err(self.error_at(&kind_token, format!("unknown light type '{}'", kind)));
+        }
+
+        let mut position = Vec3::ZERO;
+        let mut at = vec3!(0, -1, 0);
+        let mut direction = vec3!(0, -1, 0);
+        let mut color = color!(1.0);
+        let mut intensity = 1.0;
+        let mut angle: f32 = if kind == "spot" { 60.0 } else { 0.0 };
+        let mut falloff: f32 = 5.0;
+        self.block(|p, name, token| {
+            match (kind.as_str(), name) {
+                ("point" | "spot", "position") => position = p.vec3()?,
+                ("spot", "at") => at = p.vec3()?,
+                ("directional", "direction") => direction = p.vec3()?,
+                (_, "color") => color = p.vec3()?,
+                (_, "intensity") => intensity = p.number()?,
+                ("spot", "angle") => {
+                    angle = p.number()?;
+                    if !(0.0..180.0).contains(&angle) {
+                        return Err(p.error_at(token, "angle must be within [0, 180) degrees"));
+                    }
+                }
+                ("directional", "angle") => {
+                    angle = p.number()?;
+                    if !(0.0..=180.0).contains(&angle) {
+                        return Err(p.error_at(token, "angle must be within [0, 180] degrees"));
+                    }
+                }
+                ("spot", "falloff") => falloff = f32::max(p.number()?, 0.0),
+                _ => return Err(p.unknown_property(token, &kind, name)),
+            }
+            Ok(())
+        })?;
+
+        let intensity = color * intensity;
+        let light = match kind.as_str() {
+            "point" => Light::Point {
+                position,
+                intensity,
+            },
+            "spot" => {
+                let direction = at - position;
+                if direction.near_zero() {
+                    return Err(self.error_at(&kind_token, "spot light position and at coincide"));
+                }
+                let cone = 0.5 * angle.to_radians();
+                Light::Spot {
+                    position,
+                    direction: direction.normalized(),
+                    intensity,
+                    cos_falloff_start: f32::max(cone - falloff.to_radians(), 0.0).cos(),
+                    cos_cone: cone.cos(),
+                }
+            }
+            _ => {
+                if direction.near_zero() {
+                    return Err(self.error_at(&kind_token, "directional light has no direction"));
+                }
+                Light::Directional {
+                    direction: -direction.normalized(),
+                    irradiance: intensity,
+                    cos_angular_radius: (0.5 * angle.to_radians()).cos(),
+                }
+            }
+        };
+        builder.lights.push(light);
+        Ok(())
+    }
+
+    fn parse_sphere(
+        &mut self,
+        builder: &mut SceneBuilder,
+        token: &Token,
+    ) -> Result<(), SceneError> {
+        let mut center = Vec3::ZERO;
+        let mut radius = 1.0;
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "center" => center = p.vec3()?,
+                "radius" => radius = p.positive()?,
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "sphere", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "sphere has no material"))?;
+        builder
+            .world
+            .add_sphere(Sphere::new(center, radius, material));
+        Ok(())
+    }
+
+    fn parse_triangle(
+        &mut self,
+        builder: &mut SceneBuilder,
+        token: &Token,
+    ) -> Result<(), SceneError> {
+        let mut v = [None; 3];
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "v0" => v[0] = Some(p.vec3()?),
+                "v1" => v[1] = Some(p.vec3()?),
+                "v2" => v[2] = Some(p.vec3()?),
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "triangle", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "triangle has no material"))?;
+        match v {
+            [Some(v0), Some(v1), Some(v2)] => {
+                builder
+                    .world
+                    .add_triangle(Triangle::new(v0, v1, v2, material));
+                Ok(())
+            }
+            _ => Err(self.error_at(token, "triangle needs v0, v1 and v2")),
+        }
+    }
+
+    fn parse_mesh(&mut self, builder: &mut SceneBuilder, token: &Token) -> Result<(), SceneError> {
+        let mut file = None;
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "file" => file = Some(p.string()?),
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "mesh", name)),
+            }
+            Ok(())
+        })?;
+
+        let file = file.ok_or_else(|| self.error_at(token, "mesh has no file"))?;
+        let path = self.file.parent().unwrap_or(Path::new("")).join(file);
+        let material = material.unwrap_or_else(|| {
+            builder
+                .world
+                .add_material(Box::new(Lambertian::new(color!(0.8))))
+        });
+        let meshes = load_obj(&path, material, &mut builder.world.materials)
+            .map_err(|e| self.error_at(token, format!("failed to load mesh: {}", e)))?;
+        for mesh in meshes {
+            builder.world.add_mesh(mesh);
+        }
+        Ok(())
+    }
+
+    fn parse_quad(&mut self, builder: &mut SceneBuilder, token: &Token) -> Result<(), SceneError> {
+        let mut corner = Vec3::ZERO;
+        let mut u = vec3!(1, 0, 0);
+        let mut v = vec3!(0, 1, 0);
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "corner" => corner = p.vec3()?,
+                "u" => u = p.vec3()?,
+                "v" => v = p.vec3()?,
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "quad", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "quad has no material"))?;
+        if u.cross(v).near_zero() {
+            return Err(self.error_at(token, "quad edges u and v are parallel"));
+        }
+        builder.world.add_quad(Quad::new(corner, u, v, material));
+        Ok(())
+    }
+
+    fn parse_disk(&mut self, builder: &mut SceneBuilder, token: &Token) -> Result<(), SceneError> {
+        let mut center = Vec3::ZERO;
+        let mut normal = vec3!(0, 1, 0);
+        let mut radius = 1.0;
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "center" => center = p.vec3()?,
+                "normal" => normal = p.vec3()?,
+                "radius" => radius = p.positive()?,
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "disk", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "disk has no material"))?;
+        if normal.near_zero() {
+            return Err(self.error_at(token, "disk has no normal"));
+        }
+        builder
+            .world
+            .add_disk(Disk::new(center, normal, radius, material));
+        Ok(())
+    }
+
+    fn parse_plane(&mut self, builder: &mut SceneBuilder, token: &Token) -> Result<(), SceneError> {
+        let mut point = Vec3::ZERO;
+        let mut normal = vec3!(0, 1, 0);
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "point" => point = p.vec3()?,
+                "normal" => normal = p.vec3()?,
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "plane", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "plane has no material"))?;
+        if normal.near_zero() {
+            return Err(self.error_at(token, "plane has no normal"));
+        }
+        builder.world.add_plane(Plane::new(point, normal, material));
+        Ok(())
+    }
+
+    fn parse_box(&mut self, builder: &mut SceneBuilder, token: &Token) -> Result<(), SceneError> {
+        let mut min = None;
+        let mut max = None;
+        let mut material = None;
+        self.block(|p, name, token| {
+            match name {
+                "min" => min = Some(p.vec3()?),
+                "max" => max = Some(p.vec3()?),
+                "material" => material = Some(p.material(builder)?.0),
+                _ => return Err(p.unknown_property(token, "box", name)),
+            }
+            Ok(())
+        })?;
+
+        let material = material.ok_or_else(|| self.error_at(token, "box has no material"))?;
+        match (min, max) {
+            (Some(min), Some(max)) => {
+                builder.world.add_cuboid(Cuboid::new(min, max, material));
+                Ok(())
+            }
+            _ => Err(self.error_at(token, "box needs min and max")),
+        }
+    }
+}
+
+fn parse_source(
+    file: &Path,
+    source: &str,
+    builder: &mut SceneBuilder,
+    depth: usize,
+) -> Result<(), SceneError> {
+    let mut parser = Parser {
+        file,
+        tokens: lex(file, source)?,
+        pos: 0,
+    };
+    parser.parse(builder, depth)
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError {
+        file: path.to_path_buf(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    parse_scene(path, &source)
+}
+
+/// Parses a scene from `source`; includes are resolved relative to `path`.
+fn parse_scene(path: &Path, source: &str) -> Result<Scene, SceneError> {
+    let mut builder = SceneBuilder {
+        camera: CameraSettings::default(),
+        background: Background::default(),
+        lights: vec![],
+        config: RaytracerConfig::default(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        world: HittableList::new(),
+        seen: vec![],
+    };
+
+    parse_source(path, source, &mut builder, 0)?;
+
+    Ok(Scene {
+        camera: builder.camera,
+        world: builder.world,
+        background: builder.background,
+        lights: builder.lights,
+        config: builder.config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(Path::new("test.scene"), source)
+    }
+
+    fn parse_err(source: &str) -> SceneError {
+        match parse(source) {
+            Ok(_) => panic!("expected an error parsing {:?}", source),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn lex_tracks_lines_and_columns() {
+        let tokens = lex(
+            Path::new("test.scene"),
+            "film {\n  width 640 # comment\n  x 0.5 \"a b\" }",
+        )
+        .unwrap();
+        let kinds: Vec<(TokenKind, usize, usize)> = tokens
+            .into_iter()
+            .map(|t| (t.kind, t.line, t.column))
+            .collect();
+        let expected = vec![
+            (TokenKind::Ident("film".into()), 1, 1),
+            (TokenKind::LBrace, 1, 6),
+            (TokenKind::Ident("width".into()), 2, 3),
+            (TokenKind::Integer(640), 2, 9),
+            (TokenKind::Ident("x".into()), 3, 3),
+            (TokenKind::Number(0.5), 3, 5),
+            (TokenKind::Str("a b".into()), 3, 9),
+            (TokenKind::RBrace, 3, 15),
+            (TokenKind::Eof, 3, 16),
+        ];
+        assert!(kinds == expected);
+    }
+
+    #[test]
+    fn lex_errors_have_positions() {
+        let e = parse_err("film { width 10 }\ncamera { from \"open");
+        assert_eq!((e.line, e.column), (2, 15));
+        assert_eq!(e.message, "unterminated string");
+
+        let e = parse_err("  $");
+        assert_eq!((e.line, e.column), (1, 3));
+        assert_eq!(e.message, "unexpected character '$'");
+
+        let e = parse_err("sampler { spp 1.2.3 }");
+        assert_eq!((e.line, e.column), (1, 15));
+        assert_eq!(e.message, "invalid number '1.2.3'");
+    }
+
+    #[test]
+    fn errors_display_file_line_and_column() {
+        let e = parse_err("\n\n   teapot { }");
+        assert_eq!(e.to_string(), "test.scene:3:4: unknown statement 'teapot'");
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_token() {
+        let e = parse_err("film { width 10 depth 3 }");
+        assert_eq!((e.line, e.column), (1, 17));
+        assert_eq!(e.message, "unknown film property 'depth'");
+
+        let e = parse_err("sphere { center 0 0 0 radius 1 }");
+        assert_eq!((e.line, e.column), (1, 1));
+        assert_eq!(e.message, "sphere has no material");
+
+        let e = parse_err("sphere { material missing }");
+        assert_eq!((e.line, e.column), (1, 19));
+        assert_eq!(e.message, "unknown material 'missing'");
+
+        let e = parse_err("camera { fov wide }");
+        assert_eq!((e.line, e.column), (1, 14));
+        assert_eq!(e.message, "expected a number, found 'wide'");
+
+        let e = parse_err("film { width 10 }\nfilm { width 20 }");
+        assert_eq!((e.line, e.column), (2, 1));
+    }
+
+    #[test]
+    fn seeds_are_exact() {
+        for (text, seed) in [("0", 0), ("16777217", 16777217), ("4294967295", u32::MAX)] {
+            let scene = parse(&format!("sampler {{ seed {} }}", text)).ok().unwrap();
+            assert_eq!(scene.config.seed, seed);
+        }
+        for text in ["4294967296", "-1", "1.5"] {
+            let e = parse_err(&format!("sampler {{ seed {} }}", text));
+            assert_eq!((e.line, e.column), (1, 16));
+            assert!(e.message.starts_with("expected a seed"), "{}", e.message);
+        }
+    }
+
+    #[test]
+    fn counts_are_positive_integers() {
+        let scene = parse("sampler { spp 16 bounces 3 }").ok().unwrap();
+        assert_eq!(scene.config.samples_per_pixel, 16);
+        assert_eq!(scene.config.max_bounces, 3);
+
+        let e = parse_err("sampler { spp 0 }");
+        assert_eq!(e.message, "expected a positive integer, found number 0");
+        let e = parse_err("film { width 2.5 }");
+        assert_eq!(e.message, "expected a positive integer, found number 2.5");
+    }
+
+    #[test]
+    fn shapes_are_added_to_the_world() {
+        let scene = parse(
+            "material m lambertian { albedo 0.5 0.5 0.5 }\n\
+             sphere { center 0 0 0 radius 1 material m }\n\
+             quad { corner 0 0 0 u 1 0 0 v 0 1 0 material m }\n\
+             box { min 0 0 0 max 1 1 1 material m }",
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+    }
+
+    #[test]
+    fn light_angles_are_full_angles() {
+        let scene = parse(
+            "light spot { position 0 1 0 at 0 0 0 angle 90 falloff 0 }\n\
+             light directional { direction 0 -1 0 angle 90 }",
+        )
+        .ok()
+        .unwrap();
+        let half = std::f32::consts::FRAC_PI_4.cos();
+        match scene.lights[..] {
+            [Light::Spot { cos_cone, .. }, Light::Directional {
+                cos_angular_radius, ..
+            }] => {
+                assert!((cos_cone - half).abs() < 1e-6);
+                assert!((cos_angular_radius - half).abs() < 1e-6);
+            }
+            _ => panic!("expected a spot and a directional light"),
+        }
+
+        let e = parse_err("light spot { angle 180 }");
+        assert_eq!((e.line, e.column), (1, 14));
+        assert_eq!(e.message, "angle must be within [0, 180) degrees");
+    }
+
+    #[test]
+    fn metal_and_glass_roughness_takes_textures() {
+        let scene = parse(
+            "texture r noise { pattern fbm }\n\
+             material m metal { preset gold roughness r }\n\
+             material g dielectric { roughness r }\n\
+             material s metal { roughness 0 }",
+        )
+        .ok()
+        .unwrap();
+        assert!(!scene.world.materials.get(0).is_specular());
+        assert!(!scene.world.materials.get(1).is_specular());
+        assert!(scene.world.materials.get(2).is_specular());
+
+        let e = parse_err("material m metal { roughness shiny }");
+        assert_eq!((e.line, e.column), (1, 30));
+        assert_eq!(e.message, "unknown texture 'shiny'");
+    }
+
+    #[test]
+    fn emissive_materials_take_no_alpha() {
+        let e = parse_err("material lamp emissive { strength 4 alpha 0.5 }");
+        assert_eq!((e.line, e.column), (1, 37));
+        assert_eq!(e.message, "unknown emissive property 'alpha'");
+    }
+
+    #[test]
+    fn principled_weights_are_fractions() {
+        assert!(parse("material m principled { metallic 1 sheen 0 clearcoat 0.5 }").is_ok());
+
+        let e = parse_err("material m principled {\n  metallic 1.5\n}");
+        assert_eq!((e.line, e.column), (2, 12));
+        assert_eq!(e.message, "expected a number between 0 and 1");
+
+        let e = parse_err("material m principled { transmission -0.1 }");
+        assert_eq!((e.line, e.column), (1, 38));
+        assert_eq!(e.message, "expected a number between 0 and 1");
+    }
+}
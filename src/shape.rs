@@ -0,0 +1,95 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::cuboid::*;
+use crate::disk::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::plane::*;
+use crate::quad::*;
+use crate::ray::*;
+use crate::sphere::*;
+use crate::triangle::*;
+use crate::triangle_mesh::*;
+
+pub enum Shape {
+    Sphere(Sphere),
+    Triangle(Triangle),
+    Mesh(TriangleMesh),
+    Quad(Quad),
+    Disk(Disk),
+    Plane(Plane),
+    Cuboid(Cuboid),
+}
+
+impl Shape {
+    #[inline(always)]
+    pub fn material(&self) -> MaterialId {
+        match self {
+            Shape::Sphere(s) => s.material,
+            Shape::Triangle(t) => t.material,
+            Shape::Mesh(m) => m.material,
+            Shape::Quad(q) => q.material,
+            Shape::Disk(d) => d.material,
+            Shape::Plane(p) => p.material,
+            Shape::Cuboid(c) => c.material,
+        }
+    }
+
+    /// Whether the shape has finite bounds and can go into a BVH.
+    #[inline(always)]
+    pub fn is_bounded(&self) -> bool {
+        !matches!(self, Shape::Plane(_))
+    }
+
+    /// Finds the closest hit, with the surface cut out where `alpha`, the
+    /// mask of the shape's material, says so.
+    #[inline(always)]
+    pub fn hit(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        match self {
+            Shape::Sphere(sphere) => sphere.hit_masked(r, t_min, t_max, alpha, rec),
+            Shape::Triangle(triangle) => triangle.hit_masked(r, t_min, t_max, alpha, rec),
+            Shape::Mesh(mesh) => mesh.hit_masked(r, t_min, t_max, alpha, rec),
+            Shape::Quad(quad) => quad.hit_primitive(r, t_min, t_max, alpha, rec, 0),
+            Shape::Disk(disk) => disk.hit_masked(r, t_min, t_max, alpha, rec),
+            Shape::Plane(plane) => plane.hit_masked(r, t_min, t_max, alpha, rec),
+            Shape::Cuboid(cuboid) => cuboid.hit_masked(r, t_min, t_max, alpha, rec),
+        }
+    }
+
+    #[inline(always)]
+    pub fn bounding_box(&self) -> Aabb {
+        match self {
+            Shape::Sphere(sphere) => sphere.bounding_box(),
+            Shape::Triangle(triangle) => triangle.bounding_box(),
+            Shape::Mesh(mesh) => mesh.bounding_box(),
+            Shape::Quad(quad) => quad.bounding_box(),
+            Shape::Disk(disk) => disk.bounding_box(),
+            Shape::Plane(plane) => plane.bounding_box(),
+            Shape::Cuboid(cuboid) => cuboid.bounding_box(),
+        }
+    }
+
+    /// Whether anything is hit between `t_min` and `t_max`, with the surface
+    /// cut out like in `hit`.
+    #[inline(always)]
+    pub fn occluded(&self, r: Ray, t_min: f32, t_max: f32, alpha: Option<&AlphaMask>) -> bool {
+        match (self, alpha) {
+            (Shape::Mesh(mesh), _) => mesh.occluded_masked(r, t_min, t_max, alpha),
+            // The mask needs the full hit record.
+            (_, Some(_)) => self.hit(r, t_min, t_max, alpha, &mut HitRecord::new()),
+            (Shape::Sphere(sphere), None) => sphere.occluded(r, t_min, t_max),
+            (Shape::Triangle(triangle), None) => triangle.occluded(r, t_min, t_max),
+            (Shape::Quad(quad), None) => quad.occluded(r, t_min, t_max),
+            (Shape::Disk(disk), None) => disk.occluded(r, t_min, t_max),
+            (Shape::Plane(plane), None) => plane.occluded(r, t_min, t_max),
+            (Shape::Cuboid(cuboid), None) => cuboid.occluded(r, t_min, t_max),
+        }
+    }
+}
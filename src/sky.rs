@@ -0,0 +1,59 @@
+use crate::environment::*;
+use crate::physical_sky::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Background gradient seen by rays that escape the scene. It lights the
+/// scene like any emitter; make it black for closed interiors.
+#[derive(Clone, Copy)]
+pub struct Sky {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Sky {
+    pub fn new(horizon: Color, zenith: Color) -> Self {
+        Self { horizon, zenith }
+    }
+
+    #[inline(always)]
+    pub fn color(&self, r: Ray) -> Color {
+        let unit_direction = r.direction.normalized();
+        let t = 0.5 * (unit_direction.y + 1.0);
+        Vec3::lerp(self.horizon, t, self.zenith)
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self::new(color!(1.0), color!(0.5, 0.7, 1.0))
+    }
+}
+
+/// What rays that leave the scene see.
+#[derive(Clone)]
+pub enum Background {
+    Sky(Sky),
+    /// Image based lighting, also sampled as a light.
+    Environment(Arc<Environment>),
+    /// Daylight model, with the sun added as a light.
+    PhysicalSky(PhysicalSky),
+}
+
+impl Background {
+    #[inline(always)]
+    pub fn color(&self, r: Ray) -> Color {
+        match self {
+            Background::Sky(sky) => sky.color(r),
+            Background::Environment(environment) => environment.radiance(r.direction),
+            Background::PhysicalSky(sky) => sky.color(r),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Sky(Sky::default())
+    }
+}
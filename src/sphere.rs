@@ -1,28 +1,59 @@
+use crate::aabb::*;
+use crate::alpha::*;
 use crate::hittable::*;
 use crate::material::*;
 use crate::ray::*;
 use crate::vec3::*;
+use std::f32::consts::PI;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, radius: f32, material: MaterialId) -> Self {
         Self {
             center,
             radius,
             material,
         }
     }
-}
 
-impl Hittable for Sphere {
     #[inline(always)]
-    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+    fn record(&self, r: Ray, t: f32, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.p = r.at(rec.t);
+        let outward_normal = (rec.p - self.center) / self.radius;
+        rec.set_face_normal(r, outward_normal);
+        // Longitude around +y starting at -x, latitude from the south pole.
+        let theta = f32::acos((-outward_normal.y).clamp(-1.0, 1.0));
+        let phi = f32::atan2(-outward_normal.z, outward_normal.x) + PI;
+        rec.u = phi / (2.0 * PI);
+        rec.v = theta / PI;
+        // Derivatives of that parametrization; both vanish or blow up at the
+        // poles, where the shading frame falls back to an arbitrary tangent.
+        let n = outward_normal;
+        let rho = f32::sqrt(n.x * n.x + n.z * n.z);
+        let inv_rho = if rho > 0.0 { 1.0 / rho } else { 0.0 };
+        rec.dpdu = (2.0 * PI * self.radius) * vec3!(n.z, 0.0, -n.x);
+        rec.dpdv = (PI * self.radius) * vec3!(-n.y * n.x * inv_rho, rho, -n.y * n.z * inv_rho);
+        rec.material = self.material;
+        rec.primitive = 0;
+    }
+
+    /// Like `hit`, with the surface cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
         let oc = r.origin - self.center;
         let a = r.direction.lensq();
         let half_b = oc.dot(r.direction);
@@ -35,22 +66,29 @@ impl Hittable for Sphere {
         }
         let sqrtd = discriminant.sqrt();
 
-        let mut root = (-half_b - sqrtd) / a;
-
-        if root < t_min || root > t_max {
-            root = (-half_b + sqrtd) / a;
-
+        // The far root counts when the near one is out of range or cut out
+        // by the alpha mask.
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if root < t_min || root > t_max {
-                return false;
+                continue;
+            }
+            if record_hit(alpha, r, rec, |rec| self.record(r, root, rec)) {
+                return true;
             }
         }
+        false
+    }
+}
 
-        rec.t = root;
-        rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(r, outward_normal);
-        rec.material = self.material;
+impl Hittable for Sphere {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
 
-        true
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        let r = vec3!(self.radius.abs());
+        Aabb::new(self.center - r, self.center + r)
     }
 }
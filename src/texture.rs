@@ -0,0 +1,354 @@
+use crate::hittable::*;
+use crate::image::*;
+use crate::noise::*;
+use crate::vec3::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Color that varies over a surface, evaluated at hit points.
+pub trait Texture: Send + Sync {
+    fn value(&self, rec: &HitRecord) -> Color;
+
+    /// Single channel value, for inputs like roughness: the average of the
+    /// color channels.
+    #[inline(always)]
+    fn scalar(&self, rec: &HitRecord) -> f32 {
+        let c = self.value(rec);
+        (c.x + c.y + c.z) / 3.0
+    }
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    #[inline(always)]
+    fn value(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+}
+
+/// Coordinates a pattern is evaluated in: the surface's UVs, or the hit
+/// point in world space, which makes the pattern look carved out of a
+/// solid block.
+#[derive(Clone, Copy)]
+pub enum TextureSpace {
+    Uv,
+    World,
+}
+
+impl TextureSpace {
+    pub const NAMES: &'static str = "uv, world";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uv" => Some(Self::Uv),
+            "world" => Some(Self::World),
+            _ => None,
+        }
+    }
+}
+
+/// Alternates between two textures in squares (or cubes in world space),
+/// `scale` of them per unit.
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f32,
+    pub space: TextureSpace,
+}
+
+impl TextureSpace {
+    #[inline(always)]
+    fn point(self, rec: &HitRecord) -> Vec3 {
+        match self {
+            TextureSpace::Uv => vec3!(rec.u, rec.v, 0.0),
+            TextureSpace::World => rec.p,
+        }
+    }
+}
+
+impl Texture for Checker {
+    #[inline(always)]
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = self.space.point(rec) * self.scale;
+        let cells = p.x.floor() + p.y.floor() + p.z.floor();
+        if (cells as i64).rem_euclid(2) == 0 {
+            self.even.value(rec)
+        } else {
+            self.odd.value(rec)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum GradientAxis {
+    U,
+    V,
+}
+
+impl GradientAxis {
+    pub const NAMES: &'static str = "u, v";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "u" => Some(Self::U),
+            "v" => Some(Self::V),
+            _ => None,
+        }
+    }
+}
+
+/// Linear blend from `from` at 0 to `to` at 1 along a UV coordinate.
+pub struct Gradient {
+    pub from: Color,
+    pub to: Color,
+    pub axis: GradientAxis,
+}
+
+impl Texture for Gradient {
+    #[inline(always)]
+    fn value(&self, rec: &HitRecord) -> Color {
+        let t = match self.axis {
+            GradientAxis::U => rec.u,
+            GradientAxis::V => rec.v,
+        };
+        Vec3::lerp(self.from, t.clamp(0.0, 1.0), self.to)
+    }
+}
+
+/// What happens to UVs outside [0, 1].
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub const NAMES: &'static str = "repeat, clamp, mirror";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "repeat" => Some(Self::Repeat),
+            "clamp" => Some(Self::Clamp),
+            "mirror" => Some(Self::Mirror),
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    fn apply(self, i: i64, n: i64) -> i64 {
+        match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+impl Filter {
+    pub const NAMES: &'static str = "nearest, bilinear";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Self::Nearest),
+            "bilinear" => Some(Self::Bilinear),
+            _ => None,
+        }
+    }
+}
+
+/// Image mapped over the surface's UVs, with v = 0 at the bottom row.
+pub struct ImageTexture {
+    pub image: Arc<ImageBuffer>,
+    pub wrap: WrapMode,
+    pub filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<ImageBuffer>, wrap: WrapMode, filter: Filter) -> Self {
+        Self {
+            image,
+            wrap,
+            filter,
+        }
+    }
+
+    #[inline(always)]
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = self.wrap.apply(x, self.image.width as i64);
+        let y = self.wrap.apply(y, self.image.height as i64);
+        self.image.get(x as u32, y as u32)
+    }
+}
+
+impl Texture for ImageTexture {
+    #[inline(always)]
+    fn value(&self, rec: &HitRecord) -> Color {
+        if self.image.width == 0 || self.image.height == 0 {
+            return Color::ZERO;
+        }
+        let x = rec.u * self.image.width as f32;
+        let y = (1.0 - rec.v) * self.image.height as f32;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // Texel centers sit at half-integer coordinates.
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let fx = x - x0;
+                let fy = y - y0;
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = Vec3::lerp(self.texel(x0, y0), fx, self.texel(x0 + 1, y0));
+                let bottom = Vec3::lerp(self.texel(x0, y0 + 1), fx, self.texel(x0 + 1, y0 + 1));
+                Vec3::lerp(top, fy, bottom)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum NoisePattern {
+    Fbm,
+    Turbulence,
+    Marble,
+    Wood,
+    /// Distance to the nearest Worley feature point.
+    Cells,
+    /// Difference of the two nearest Worley distances, dark along the cell
+    /// borders.
+    Cracks,
+}
+
+impl NoisePattern {
+    pub const NAMES: &'static str = "fbm, turbulence, marble, wood, cells, cracks";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fbm" => Some(Self::Fbm),
+            "turbulence" => Some(Self::Turbulence),
+            "marble" => Some(Self::Marble),
+            "wood" => Some(Self::Wood),
+            "cells" => Some(Self::Cells),
+            "cracks" => Some(Self::Cracks),
+            _ => None,
+        }
+    }
+}
+
+/// Procedural noise pattern, mapped from `from` at 0 to `to` at 1.
+/// `scale` is the frequency of the first octave.
+pub struct NoiseTexture {
+    pub noise: Noise,
+    pub basis: NoiseBasis,
+    pub pattern: NoisePattern,
+    pub scale: f32,
+    pub octaves: u32,
+    pub space: TextureSpace,
+    pub from: Color,
+    pub to: Color,
+}
+
+impl Texture for NoiseTexture {
+    #[inline(always)]
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = self.space.point(rec) * self.scale;
+        let noise = &self.noise;
+        let t = match self.pattern {
+            NoisePattern::Fbm => 0.5 + 0.5 * noise.fbm(self.basis, p, self.octaves),
+            NoisePattern::Turbulence => noise.turbulence(self.basis, p, self.octaves),
+            NoisePattern::Marble => {
+                // One vein per unit along x, bent by turbulence.
+                let turbulence = noise.turbulence(self.basis, p, self.octaves);
+                0.5 + 0.5 * f32::sin(PI * (p.x + 4.0 * turbulence))
+            }
+            NoisePattern::Wood => {
+                // Rings around the y axis, wobbled by low frequency noise.
+                let r = f32::sqrt(p.x * p.x + p.z * p.z);
+                let rings = 4.0 * r + noise.fbm(self.basis, p * 0.5, self.octaves);
+                rings - rings.floor()
+            }
+            NoisePattern::Cells => noise.worley(p).0,
+            NoisePattern::Cracks => {
+                let (f1, f2) = noise.worley(p);
+                f2 - f1
+            }
+        };
+        Vec3::lerp(self.from, t.clamp(0.0, 1.0), self.to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(space: TextureSpace) -> Checker {
+        Checker {
+            even: Arc::new(SolidColor::new(color!(0.0))),
+            odd: Arc::new(SolidColor::new(color!(1.0))),
+            scale: 2.0,
+            space,
+        }
+    }
+
+    #[test]
+    fn checker_alternates_across_zero() {
+        let world = checker(TextureSpace::World);
+        let mut rec = HitRecord::new();
+        // Cells are half a unit wide; flooring keeps them alternating on
+        // both sides of the origin instead of doubling up around it.
+        for (x, expected) in [(-0.75, 0.0), (-0.25, 1.0), (0.25, 0.0), (0.75, 1.0)] {
+            rec.p = vec3!(x, 0.1, 0.1);
+            assert_eq!(world.value(&rec).x, expected);
+        }
+        rec.p = vec3!(-0.25, -0.25, -0.25);
+        assert_eq!(world.value(&rec).x, 1.0);
+
+        let uv = checker(TextureSpace::Uv);
+        rec.u = -0.25;
+        rec.v = 0.25;
+        assert_eq!(uv.value(&rec).x, 1.0);
+    }
+
+    #[test]
+    fn wrap_modes_map_indices_into_range() {
+        let indices = -5..9;
+        let repeat: Vec<i64> = indices
+            .clone()
+            .map(|i| WrapMode::Repeat.apply(i, 4))
+            .collect();
+        let clamp: Vec<i64> = indices
+            .clone()
+            .map(|i| WrapMode::Clamp.apply(i, 4))
+            .collect();
+        let mirror: Vec<i64> = indices.map(|i| WrapMode::Mirror.apply(i, 4)).collect();
+        assert_eq!(repeat, [3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]);
+        assert_eq!(clamp, [0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3]);
+        assert_eq!(mirror, [3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct State {
+    queue: VecDeque<Job>,
+    /// Jobs queued or currently running.
+    pending: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    job_available: Condvar,
+    all_done: Condvar,
+}
+
+/// Fixed set of worker threads pulling jobs from a shared queue. Workers
+/// sleep while there is nothing to do and the pool can be reused for any
+/// number of renders; threads are joined when it is dropped.
+pub struct ThreadPool {
+    workers: Vec<thread::JoinHandle<()>>,
+    shared: Arc<Shared>,
+}
+
+/// Marks a job as finished even if it panics, so `wait` can't hang.
+struct JobGuard<'a>(&'a Shared);
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.0.all_done.notify_all();
+        }
+    }
+}
+
+fn worker_loop(shared: &Shared) {
+    loop {
+        let job = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if let Some(job) = state.queue.pop_front() {
+                    break job;
+                }
+                if state.shutdown {
+                    return;
+                }
+                state = shared.job_available.wait(state).unwrap();
+            }
+        };
+
+        let _guard = JobGuard(shared);
+        // A panicking job has already reported itself through the panic
+        // hook; the worker stays alive so the pool keeps its size.
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+impl ThreadPool {
+    pub fn new(threads: u32) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                pending: 0,
+                shutdown: false,
+            }),
+            job_available: Condvar::new(),
+            all_done: Condvar::new(),
+        });
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("render-{}", i))
+                    .spawn(move || worker_loop(&shared))
+                    .expect("failed to spawn render thread")
+            })
+            .collect();
+
+        Self { workers, shared }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queue.push_back(Box::new(job));
+        state.pending += 1;
+        self.shared.job_available.notify_one();
+    }
+
+    /// Blocks until every job submitted so far has finished.
+    pub fn wait(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.pending > 0 {
+            state = self.shared.all_done.wait(state).unwrap();
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.shutdown = true;
+            self.shared.job_available.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn runs_every_job() {
+        let pool = ThreadPool::new(3);
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..100 {
+            let count = Arc::clone(&count);
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        pool.wait();
+        assert_eq!(count.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn survives_panicking_jobs() {
+        let pool = ThreadPool::new(2);
+        for _ in 0..4 {
+            pool.execute(|| panic!("job failed"));
+        }
+        pool.wait();
+
+        // Both workers are still there to pick up new work: each of these
+        // jobs blocks until the other one has started.
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        for _ in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+        pool.wait();
+        assert_eq!(pool.thread_count(), 2);
+    }
+}
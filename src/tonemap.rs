@@ -0,0 +1,232 @@
+use crate::vec3::*;
+
+/// Display transform applied to the linear radiance before it is encoded
+/// into a low dynamic range image.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    /// Scales by the white point and clamps.
+    Linear,
+    Reinhard,
+    /// Reinhard with a white point: luminance at the white point maps to 1.
+    ReinhardExtended,
+    /// Stephen Hill's fit of the ACES reference and output transforms.
+    Aces,
+    /// John Hable's Uncharted 2 filmic curve.
+    Hable,
+    /// Polynomial fit of Blender's AgX base look.
+    Agx,
+}
+
+impl ToneMapOperator {
+    pub const NAMES: &'static str = "linear, reinhard, reinhard-extended, aces, hable, agx";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "linear" | "clamp" => Some(ToneMapOperator::Linear),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "reinhard-extended" => Some(ToneMapOperator::ReinhardExtended),
+            "aces" => Some(ToneMapOperator::Aces),
+            "hable" | "uncharted" => Some(ToneMapOperator::Hable),
+            "agx" => Some(ToneMapOperator::Agx),
+            _ => None,
+        }
+    }
+
+    /// White point used when none is configured.
+    fn default_white_point(self) -> f32 {
+        match self {
+            ToneMapOperator::Linear | ToneMapOperator::Reinhard => 1.0,
+            ToneMapOperator::ReinhardExtended => 4.0,
+            ToneMapOperator::Aces | ToneMapOperator::Agx => 1.0,
+            ToneMapOperator::Hable => 11.2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops, applied before the operator.
+    pub exposure: f32,
+    /// Linear value mapped to white by the operators that have one
+    /// (linear, extended Reinhard and Hable). `None` picks the operator's
+    /// default.
+    pub white_point: Option<f32>,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Linear,
+            exposure: 0.0,
+            white_point: None,
+        }
+    }
+}
+
+#[inline(always)]
+fn luminance(c: Color) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+#[inline(always)]
+fn mul_matrix(m: &[[f32; 3]; 3], c: Color) -> Color {
+    vec3!(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z
+    )
+}
+
+#[inline(always)]
+fn map_channels(c: Color, f: impl Fn(f32) -> f32) -> Color {
+    vec3!(f(c.x), f(c.y), f(c.z))
+}
+
+#[inline(always)]
+fn scale_luminance(c: Color, f: impl Fn(f32) -> f32) -> Color {
+    let l = luminance(c);
+    if l <= 0.0 {
+        return Color::ZERO;
+    }
+    c * (f(l) / l)
+}
+
+fn aces(c: Color) -> Color {
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let c = mul_matrix(&INPUT, c);
+    let c = map_channels(c, |v| {
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081)
+    });
+    mul_matrix(&OUTPUT, c)
+}
+
+#[inline(always)]
+fn hable_curve(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    // (x (A x + C B) + D E) / (x (A x + B) + D F) - E / F, rearranged so
+    // that black comes out as exactly zero.
+    x * (F * (A * x + C * B) - E * (A * x + B)) / (F * (x * (A * x + B) + D * F))
+}
+
+fn agx(c: Color) -> Color {
+    const INSET: [[f32; 3]; 3] = [
+        [0.8424791, 0.0784336, 0.07922375],
+        [0.04232824, 0.8784686, 0.07916613],
+        [0.04237565, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.05289685, 1.151903, -0.09896118],
+        [-0.05297164, -0.09804345, 1.151074],
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let c = mul_matrix(&INSET, c);
+    let c = map_channels(c, |v| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    // The curve produces display values, bring them back to linear so the
+    // output encoding applies the same way for every operator.
+    let c = mul_matrix(&OUTSET, c);
+    map_channels(c, |v| v.max(0.0).powf(2.2))
+}
+
+impl ToneMapping {
+    /// Maps linear radiance to linear display values in `[0, 1]`.
+    pub fn apply(&self, color: Color) -> Color {
+        let white = self
+            .white_point
+            .unwrap_or(self.operator.default_white_point());
+        let c = Vec3::max(color * self.exposure.exp2(), Color::ZERO);
+
+        let mapped = match self.operator {
+            ToneMapOperator::Linear => c / white,
+            ToneMapOperator::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMapOperator::ReinhardExtended => {
+                scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapOperator::Aces => aces(c),
+            ToneMapOperator::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                let scale = 1.0 / hable_curve(white);
+                map_channels(c, |v| hable_curve(v * EXPOSURE_BIAS) * scale)
+            }
+            ToneMapOperator::Agx => agx(c),
+        };
+
+        map_channels(mapped, |v| v.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(operator: ToneMapOperator) -> ToneMapping {
+        ToneMapping {
+            operator,
+            ..ToneMapping::default()
+        }
+    }
+
+    #[test]
+    fn linear_without_exposure_is_the_identity() {
+        let c = color!(0.25, 0.5, 0.75);
+        assert!(mapping(ToneMapOperator::Linear).apply(c) == c);
+        assert!(mapping(ToneMapOperator::Linear).apply(color!(3.0)) == color!(1.0));
+    }
+
+    #[test]
+    fn extended_reinhard_maps_white_to_one() {
+        for white in [1.0, 4.0, 10.0] {
+            let tone_mapping = ToneMapping {
+                white_point: Some(white),
+                ..mapping(ToneMapOperator::ReinhardExtended)
+            };
+            let c = tone_mapping.apply(color!(white));
+            for axis in 0..3 {
+                assert!((c[axis] - 1.0).abs() < 1e-5, "white {}: {}", white, c[axis]);
+            }
+        }
+    }
+
+    #[test]
+    fn curves_map_black_to_zero_and_stay_in_range() {
+        for operator in [
+            ToneMapOperator::Aces,
+            ToneMapOperator::Hable,
+            ToneMapOperator::Agx,
+        ] {
+            let tone_mapping = mapping(operator);
+            assert!(tone_mapping.apply(Color::ZERO) == Color::ZERO);
+            for v in [1e-4, 0.01, 0.18, 0.5, 1.0, 4.0, 100.0, 1e6] {
+                for c in [color!(v), color!(v, 0.1 * v, 0.01 * v)] {
+                    let mapped = tone_mapping.apply(c);
+                    for axis in 0..3 {
+                        assert!((0.0..=1.0).contains(&mapped[axis]));
+                    }
+                }
+            }
+        }
+    }
+}
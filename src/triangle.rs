@@ -0,0 +1,181 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    pub material: MaterialId,
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: MaterialId) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            material,
+        }
+    }
+}
+
+#[inline(always)]
+fn permute(v: Vec3, kx: usize, ky: usize, kz: usize) -> Vec3 {
+    vec3!(v[kx], v[ky], v[kz])
+}
+
+#[inline(always)]
+fn max_dimension(v: Vec3) -> usize {
+    if v.x > v.y && v.x > v.z {
+        0
+    } else if v.y > v.z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013).
+/// Returns the ray parameter and the barycentric coordinates of the hit.
+#[inline(always)]
+pub fn intersect_triangle(
+    r: Ray,
+    p0: Vec3,
+    p1: Vec3,
+    p2: Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, [f32; 3])> {
+    let d = r.direction;
+    let kz = max_dimension(vec3!(d.x.abs(), d.y.abs(), d.z.abs()));
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+
+    let d = permute(d, kx, ky, kz);
+    let mut p0t = permute(p0 - r.origin, kx, ky, kz);
+    let mut p1t = permute(p1 - r.origin, kx, ky, kz);
+    let mut p2t = permute(p2 - r.origin, kx, ky, kz);
+
+    let sx = -d.x / d.z;
+    let sy = -d.y / d.z;
+    let sz = 1.0 / d.z;
+    p0t.x += sx * p0t.z;
+    p0t.y += sy * p0t.z;
+    p1t.x += sx * p1t.z;
+    p1t.y += sy * p1t.z;
+    p2t.x += sx * p2t.z;
+    p2t.y += sy * p2t.z;
+
+    let mut e0 = p1t.x * p2t.y - p1t.y * p2t.x;
+    let mut e1 = p2t.x * p0t.y - p2t.y * p0t.x;
+    let mut e2 = p0t.x * p1t.y - p0t.y * p1t.x;
+
+    // Edges exactly on the ray are resolved in double precision so that
+    // neighbouring triangles never leave a crack between them.
+    if e0 == 0.0 || e1 == 0.0 || e2 == 0.0 {
+        e0 = (p1t.x as f64 * p2t.y as f64 - p1t.y as f64 * p2t.x as f64) as f32;
+        e1 = (p2t.x as f64 * p0t.y as f64 - p2t.y as f64 * p0t.x as f64) as f32;
+        e2 = (p0t.x as f64 * p1t.y as f64 - p0t.y as f64 * p1t.x as f64) as f32;
+    }
+
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+
+    let det = e0 + e1 + e2;
+    if det == 0.0 {
+        return None;
+    }
+
+    p0t.z *= sz;
+    p1t.z *= sz;
+    p2t.z *= sz;
+    let t_scaled = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
+
+    if det < 0.0 && (t_scaled >= t_min * det || t_scaled < t_max * det) {
+        return None;
+    }
+    if det > 0.0 && (t_scaled <= t_min * det || t_scaled > t_max * det) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some((
+        t_scaled * inv_det,
+        [e0 * inv_det, e1 * inv_det, e2 * inv_det],
+    ))
+}
+
+/// Partial derivatives of position with respect to u and v over a triangle
+/// with vertices `p` and texture coordinates `uv`. Degenerate UVs fall back
+/// to an arbitrary pair of tangents of `normal`.
+#[inline(always)]
+pub fn triangle_derivatives(p: [Vec3; 3], uv: [[f32; 2]; 3], normal: Vec3) -> (Vec3, Vec3) {
+    let duv02 = [uv[0][0] - uv[2][0], uv[0][1] - uv[2][1]];
+    let duv12 = [uv[1][0] - uv[2][0], uv[1][1] - uv[2][1]];
+    let dp02 = p[0] - p[2];
+    let dp12 = p[1] - p[2];
+    let det = duv02[0] * duv12[1] - duv02[1] * duv12[0];
+    if det.abs() < 1e-12 {
+        return normal.orthonormal_basis();
+    }
+    let inv_det = 1.0 / det;
+    (
+        (duv12[1] * dp02 - duv02[1] * dp12) * inv_det,
+        (duv02[0] * dp12 - duv12[0] * dp02) * inv_det,
+    )
+}
+
+/// UVs a triangle without texture coordinates gets at its vertices.
+pub const DEFAULT_TRIANGLE_UVS: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+
+impl Triangle {
+    /// Like `hit`, with the surface cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let (t, b) = match intersect_triangle(r, self.v0, self.v1, self.v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        record_hit(alpha, r, rec, |rec| {
+            rec.t = t;
+            rec.p = b[0] * self.v0 + b[1] * self.v1 + b[2] * self.v2;
+            let outward_normal = (self.v1 - self.v0).cross(self.v2 - self.v0).normalized();
+            rec.set_face_normal(r, outward_normal);
+            rec.u = b[1] + b[2];
+            rec.v = b[2];
+            (rec.dpdu, rec.dpdv) = triangle_derivatives(
+                [self.v0, self.v1, self.v2],
+                DEFAULT_TRIANGLE_UVS,
+                outward_normal,
+            );
+            rec.material = self.material;
+            rec.primitive = 0;
+        })
+    }
+}
+
+impl Hittable for Triangle {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.v0, self.v1).grow(self.v2)
+    }
+}
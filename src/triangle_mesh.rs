@@ -0,0 +1,185 @@
+use crate::aabb::*;
+use crate::alpha::*;
+use crate::bvh::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::triangle::*;
+use crate::vec3::*;
+
+/// Indexed triangle mesh. Positions, normals and UVs live in shared buffers
+/// and every face indexes into them, with one index list per attribute (the
+/// way OBJ files store them). The normal and UV index lists are either empty
+/// or have one entry per face.
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<[u32; 3]>,
+    pub normal_indices: Vec<[u32; 3]>,
+    pub uv_indices: Vec<[u32; 3]>,
+    pub material: MaterialId,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[u32; 3]>, material: MaterialId) -> Self {
+        let bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|f| {
+                Aabb::new(positions[f[0] as usize], positions[f[1] as usize])
+                    .grow(positions[f[2] as usize])
+            })
+            .collect();
+        let (bvh, _) = Bvh::build(&bounds);
+
+        Self {
+            positions,
+            normals: vec![],
+            uvs: vec![],
+            indices,
+            normal_indices: vec![],
+            uv_indices: vec![],
+            material,
+            bvh,
+        }
+    }
+
+    /// Adds per-vertex normals, interpolated across faces for smooth shading.
+    pub fn with_normals(mut self, normals: Vec<Vec3>, normal_indices: Vec<[u32; 3]>) -> Self {
+        assert_eq!(normal_indices.len(), self.indices.len());
+        self.normals = normals;
+        self.normal_indices = normal_indices;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<[f32; 2]>, uv_indices: Vec<[u32; 3]>) -> Self {
+        assert_eq!(uv_indices.len(), self.indices.len());
+        self.uvs = uvs;
+        self.uv_indices = uv_indices;
+        self
+    }
+
+    #[inline(always)]
+    fn hit_face(
+        &self,
+        face: usize,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let [i0, i1, i2] = self.indices[face];
+        let p0 = self.positions[i0 as usize];
+        let p1 = self.positions[i1 as usize];
+        let p2 = self.positions[i2 as usize];
+
+        let (t, b) = match intersect_triangle(r, p0, p1, p2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        record_hit(alpha, r, rec, |rec| self.record_face(face, r, t, b, rec))
+    }
+
+    #[inline(always)]
+    fn record_face(&self, face: usize, r: Ray, t: f32, b: [f32; 3], rec: &mut HitRecord) {
+        let [i0, i1, i2] = self.indices[face];
+        let p0 = self.positions[i0 as usize];
+        let p1 = self.positions[i1 as usize];
+        let p2 = self.positions[i2 as usize];
+
+        rec.t = t;
+        rec.p = b[0] * p0 + b[1] * p1 + b[2] * p2;
+        let outward_normal = (p1 - p0).cross(p2 - p0).normalized();
+        rec.set_face_normal(r, outward_normal);
+
+        if !self.normal_indices.is_empty() {
+            let [n0, n1, n2] = self.normal_indices[face];
+            let shading_normal = b[0] * self.normals[n0 as usize]
+                + b[1] * self.normals[n1 as usize]
+                + b[2] * self.normals[n2 as usize];
+            if !shading_normal.near_zero() {
+                let shading_normal = shading_normal.normalized();
+                // Keep the interpolated normal on the same side as the face.
+                rec.normal = if shading_normal.dot(rec.normal) < 0.0 {
+                    -shading_normal
+                } else {
+                    shading_normal
+                };
+            }
+        }
+
+        let [uv0, uv1, uv2] = if self.uv_indices.is_empty() {
+            DEFAULT_TRIANGLE_UVS
+        } else {
+            self.uv_indices[face].map(|t| self.uvs[t as usize])
+        };
+        rec.u = b[0] * uv0[0] + b[1] * uv1[0] + b[2] * uv2[0];
+        rec.v = b[0] * uv0[1] + b[1] * uv1[1] + b[2] * uv2[1];
+        (rec.dpdu, rec.dpdv) = triangle_derivatives([p0, p1, p2], [uv0, uv1, uv2], outward_normal);
+
+        rec.material = self.material;
+        rec.primitive = face as u32;
+    }
+}
+
+impl TriangleMesh {
+    /// Like `hit`, with the faces cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn hit_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+        rec: &mut HitRecord,
+    ) -> bool {
+        self.bvh
+            .hit(r, t_min, t_max, rec, |face, closest_so_far, rec| {
+                self.hit_face(face, r, t_min, closest_so_far, alpha, rec)
+            })
+    }
+
+    /// Like `occluded`, with the faces cut out where `alpha` says so.
+    #[inline(always)]
+    pub fn occluded_masked(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        alpha: Option<&AlphaMask>,
+    ) -> bool {
+        if alpha.is_some() {
+            // The mask needs the full hit record.
+            return self.bvh.occluded(r, t_min, t_max, |face| {
+                self.hit_face(face, r, t_min, t_max, alpha, &mut HitRecord::new())
+            });
+        }
+        self.bvh.occluded(r, t_min, t_max, |face| {
+            let [i0, i1, i2] = self.indices[face];
+            let p0 = self.positions[i0 as usize];
+            let p1 = self.positions[i1 as usize];
+            let p2 = self.positions[i2 as usize];
+            intersect_triangle(r, p0, p1, p2, t_min, t_max).is_some()
+        })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    #[inline(always)]
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.hit_masked(r, t_min, t_max, None, rec)
+    }
+
+    #[inline(always)]
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    #[inline(always)]
+    fn occluded(&self, r: Ray, t_min: f32, t_max: f32) -> bool {
+        self.occluded_masked(r, t_min, t_max, None)
+    }
+}
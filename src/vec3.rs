@@ -106,6 +106,21 @@ impl Vec3 {
         (1.0 - t) * a + t * b
     }
 
+    #[inline(always)]
+    pub fn min(a: Vec3, b: Vec3) -> Vec3 {
+        vec3!(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+    }
+
+    #[inline(always)]
+    pub fn max(a: Vec3, b: Vec3) -> Vec3 {
+        vec3!(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+    }
+
+    #[inline(always)]
+    pub fn recip(self) -> Vec3 {
+        vec3!(1.0 / self.x, 1.0 / self.y, 1.0 / self.z)
+    }
+
     #[inline(always)]
     pub fn hadamard(a: Vec3, b: Vec3) -> Vec3 {
         Vec3 {
@@ -116,23 +131,27 @@ impl Vec3 {
     }
 
     #[inline(always)]
-    pub fn random() -> Vec3 {
-        vec3!(randomf32(), randomf32(), randomf32())
+    pub fn random(rng: &mut Rng) -> Vec3 {
+        vec3!(rng.randomf32(), rng.randomf32(), rng.randomf32())
     }
 
     #[inline(always)]
-    pub fn random_range(min: f32, max: f32) -> Vec3 {
+    pub fn random_range(rng: &mut Rng, min: f32, max: f32) -> Vec3 {
         vec3!(
-            randomf32_range(min, max),
-            randomf32_range(min, max),
-            randomf32_range(min, max)
+            rng.randomf32_range(min, max),
+            rng.randomf32_range(min, max),
+            rng.randomf32_range(min, max)
         )
     }
 
     #[inline(always)]
-    pub fn random_in_unit_disk() -> Vec3 {
+    pub fn random_in_unit_disk(rng: &mut Rng) -> Vec3 {
         loop {
-            let p = vec3!(randomf32_range(-1.0, 1.0), randomf32_range(-1.0, 1.0), 0.0);
+            let p = vec3!(
+                rng.randomf32_range(-1.0, 1.0),
+                rng.randomf32_range(-1.0, 1.0),
+                0.0
+            );
             if p.lensq() >= 1.0 {
                 continue;
             }
@@ -141,9 +160,9 @@ impl Vec3 {
     }
 
     #[inline(always)]
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere(rng: &mut Rng) -> Vec3 {
         loop {
-            let p = Vec3::random_range(-1.0, 1.0);
+            let p = Vec3::random_range(rng, -1.0, 1.0);
             if p.lensq() >= 1.0 {
                 continue;
             }
@@ -152,8 +171,8 @@ impl Vec3 {
     }
 
     #[inline(always)]
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().normalized()
+    pub fn random_unit_vector(rng: &mut Rng) -> Vec3 {
+        Vec3::random_in_unit_sphere(rng).normalized()
     }
 
     #[inline(always)]
@@ -174,6 +193,19 @@ impl Vec3 {
         let r_out_parallel = -f32::sqrt(f32::abs(1.0 - r_out_perp.lensq())) * n;
         r_out_perp + r_out_parallel
     }
+
+    /// Two unit vectors that form an orthonormal basis with this one, which
+    /// must be normalized (Duff et al. 2017).
+    #[inline(always)]
+    pub fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            vec3!(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            vec3!(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
 }
 
 impl Add for Vec3 {
@@ -331,14 +363,23 @@ impl Neg for Vec3 {
     }
 }
 
-impl PartialEq for Vec3 {
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
     #[inline(always)]
-    fn eq(&self, other: &Vec3) -> bool {
-        self.x == other.x && self.y == other.y && self.z == other.z
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index {} out of range", axis),
+        }
     }
+}
 
+impl PartialEq for Vec3 {
     #[inline(always)]
-    fn ne(&self, other: &Vec3) -> bool {
-        self.x != other.x || self.y != other.y || self.z != other.z
+    fn eq(&self, other: &Vec3) -> bool {
+        self.x == other.x && self.y == other.y && self.z == other.z
     }
 }